@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+
+use crate::error::{Error, Result};
+use crate::runner::PartRun;
+use crate::solution::Answer;
+use crate::utils;
+
+/// Known-correct answers keyed by `(day, part)`.
+pub type Answers = HashMap<(u32, u8), Answer>;
+
+/// Parses the small TOML subset used by `answers.toml`:
+///
+/// ```toml
+/// [day01]
+/// part1 = 433
+/// part2 = "abc"
+/// ```
+pub fn parse_answers(text: &str) -> Result<Answers> {
+    let mut answers = HashMap::new();
+    let mut day: Option<u32> = None;
+    for (n, raw) in text.lines().enumerate() {
+        let line = strip_comment(raw).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let err = |msg: &str| Error::parse(n + 1, utils::column_of(raw, line), msg);
+
+        if line.starts_with('[') && line.ends_with(']') {
+            let name = line[1..line.len() - 1].trim();
+            let number = name.trim_start_matches("day");
+            day = Some(
+                number
+                    .parse()
+                    .map_err(|_| err("expected a [dayNN] table"))?,
+            );
+            continue;
+        }
+
+        let eq = line
+            .find('=')
+            .ok_or_else(|| err("expected `key = value`"))?;
+        let key = line[..eq].trim();
+        let value = line[eq + 1..].trim();
+        let part = match key {
+            "part1" => 1,
+            "part2" => 2,
+            _ => return Err(err("keys must be part1 or part2")),
+        };
+        let day = day.ok_or_else(|| err("answer outside of a [dayNN] table"))?;
+        let answer =
+            parse_value(value).map_err(|e| Error::parse(n + 1, utils::column_of(raw, value), e))?;
+        answers.insert((day, part), answer);
+    }
+    Ok(answers)
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '\\' if in_string => escaped = !escaped,
+            '"' if !escaped => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => escaped = false,
+        }
+    }
+    line
+}
+
+fn parse_value(value: &str) -> std::result::Result<Answer, String> {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        let mut s = String::new();
+        let mut chars = value[1..value.len() - 1].chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                s.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('"') => s.push('"'),
+                Some('\\') => s.push('\\'),
+                Some('n') => s.push('\n'),
+                Some('t') => s.push('\t'),
+                _ => return Err("unsupported escape in string".to_string()),
+            }
+        }
+        Ok(Answer::Text(s))
+    } else {
+        value
+            .replace('_', "")
+            .parse::<i64>()
+            .map(Answer::Number)
+            .map_err(|_| format!("not a number or string: {}", value))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    Pass,
+    Fail(Answer),
+    Missing,
+    Error,
+}
+
+pub fn verdict(run: &PartRun, answers: &Answers) -> Verdict {
+    match (&run.answer, answers.get(&(run.day, run.part))) {
+        (Err(_), _) => Verdict::Error,
+        (Ok(_), None) => Verdict::Missing,
+        (Ok(actual), Some(expected)) if actual == expected => Verdict::Pass,
+        (Ok(_), Some(expected)) => Verdict::Fail(expected.clone()),
+    }
+}
+
+/// Renders a pass/fail report for each run followed by a one-line summary.
+pub fn render_report(runs: &[PartRun], answers: &Answers) -> String {
+    let mut out = String::new();
+    let mut counts = [0usize; 4];
+    for run in runs {
+        let actual = match &run.answer {
+            Ok(a) => a.to_string(),
+            Err(e) => e.to_string(),
+        };
+        let (i, line) = match verdict(run, answers) {
+            Verdict::Pass => (0, format!("pass     {}", actual)),
+            Verdict::Fail(expected) => (1, format!("FAIL     {} (expected {})", actual, expected)),
+            Verdict::Missing => (2, format!("missing  {}", actual)),
+            Verdict::Error => (3, format!("ERROR    {}", actual)),
+        };
+        counts[i] += 1;
+        out.push_str(&format!("day {:>2} part {}  {}\n", run.day, run.part, line));
+    }
+    out.push_str(&format!(
+        "{} passed, {} failed, {} missing, {} errors\n",
+        counts[0], counts[1], counts[2], counts[3]
+    ));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_answers() {
+        let text = r#"
+            # known answers
+            [day01]
+            part1 = 433
+            part2 = -1_000 # underscores allowed
+
+            [day02]
+            part2 = "ab#c\"d"
+        "#;
+        let answers = parse_answers(text).unwrap();
+        assert_eq!(answers.len(), 3);
+        assert_eq!(answers[&(1, 1)], Answer::Number(433));
+        assert_eq!(answers[&(1, 2)], Answer::Number(-1000));
+        assert_eq!(answers[&(2, 2)], Answer::from("ab#c\"d"));
+
+        assert!(parse_answers("part1 = 3").is_err());
+        assert!(parse_answers("[day01]\npart3 = 3").is_err());
+        assert_eq!(
+            parse_answers("[day01]\n  part1 = x").unwrap_err(),
+            Error::parse(2, 11, "not a number or string: x")
+        );
+    }
+
+    #[test]
+    fn test_verdict() {
+        let answers = parse_answers("[day01]\npart1 = 433\npart2 = 256").unwrap();
+        let run = |part, answer| PartRun {
+            day: 1,
+            part,
+            answer,
+            elapsed: Duration::from_secs(0),
+        };
+        assert_eq!(
+            verdict(&run(1, Ok(Answer::Number(433))), &answers),
+            Verdict::Pass
+        );
+        assert_eq!(
+            verdict(&run(2, Ok(Answer::Number(1))), &answers),
+            Verdict::Fail(Answer::Number(256))
+        );
+        assert_eq!(
+            verdict(&run(2, Err(Error::Panic("boom".to_string()))), &answers),
+            Verdict::Error
+        );
+
+        let empty = Answers::new();
+        assert_eq!(
+            verdict(&run(1, Ok(Answer::Number(433))), &empty),
+            Verdict::Missing
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use std::hint::black_box;
+use std::num::NonZeroUsize;
+use std::time::{Duration, Instant};
+
+use crate::error::Result;
+use crate::runner::format_duration;
+use crate::solution::DynSolution;
+
+/// Summary statistics over repeated timings of one phase.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stats {
+    pub min: Duration,
+    pub median: Duration,
+    pub mean: Duration,
+    pub stddev: Duration,
+}
+
+impl Stats {
+    /// Computes statistics over at least one sample. The standard deviation is the sample
+    /// (n - 1) deviation, and zero for a single sample.
+    pub fn from_samples(samples: &[Duration]) -> Stats {
+        let mut sorted = samples.to_vec();
+        sorted.sort();
+        let n = sorted.len();
+        let median = if n % 2 == 1 {
+            sorted[n / 2]
+        } else {
+            (sorted[n / 2 - 1] + sorted[n / 2]) / 2
+        };
+
+        let secs: Vec<f64> = sorted.iter().map(|d| d.as_secs_f64()).collect();
+        let mean = secs.iter().sum::<f64>() / n as f64;
+        let variance = if n > 1 {
+            secs.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / (n - 1) as f64
+        } else {
+            0.0
+        };
+
+        Stats {
+            min: sorted[0],
+            median,
+            mean: Duration::from_secs_f64(mean),
+            stddev: Duration::from_secs_f64(variance.sqrt()),
+        }
+    }
+}
+
+/// Timings for each phase of one day.
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub day: u32,
+    pub iterations: usize,
+    pub parse: Stats,
+    pub part1: Stats,
+    pub part2: Stats,
+}
+
+fn sample<T>(iterations: usize, mut f: impl FnMut() -> Result<T>) -> Result<Stats> {
+    let mut samples = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        let start = Instant::now();
+        // Keeps the compiler from discarding or hoisting work whose result goes unused.
+        black_box(f()?);
+        samples.push(start.elapsed());
+    }
+    Ok(Stats::from_samples(&samples))
+}
+
+/// Runs parse, part 1 and part 2 of a day `iterations` times each. The parts reuse one parsed
+/// input so that their timings do not include parsing.
+pub fn bench_day(
+    day: u32,
+    solution: &dyn DynSolution,
+    text: &str,
+    iterations: NonZeroUsize,
+) -> Result<BenchReport> {
+    let iterations = iterations.get();
+    let parse = sample(iterations, || solution.dyn_parse(text))?;
+    let input = solution.dyn_parse(text)?;
+    let part1 = sample(iterations, || solution.dyn_part1(input.as_ref()))?;
+    let part2 = sample(iterations, || solution.dyn_part2(input.as_ref()))?;
+    Ok(BenchReport {
+        day,
+        iterations,
+        parse,
+        part1,
+        part2,
+    })
+}
+
+pub fn render_report(report: &BenchReport) -> String {
+    let mut out = format!(
+        "Day {} ({} iterations)\n{:<8}  {:>10}  {:>10}  {:>10}  {:>10}\n",
+        report.day, report.iterations, "Phase", "min", "median", "mean", "stddev"
+    );
+    for (name, stats) in &[
+        ("parse", report.parse),
+        ("part 1", report.part1),
+        ("part 2", report.part2),
+    ] {
+        out.push_str(&format!(
+            "{:<8}  {:>10}  {:>10}  {:>10}  {:>10}\n",
+            name,
+            format_duration(stats.min),
+            format_duration(stats.median),
+            format_duration(stats.mean),
+            format_duration(stats.stddev)
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solution;
+
+    #[test]
+    fn test_stats() {
+        let ms = Duration::from_millis;
+        let stats = Stats::from_samples(&[ms(4), ms(2), ms(6), ms(8)]);
+        assert_eq!(stats.min, ms(2));
+        assert_eq!(stats.median, ms(5));
+        assert_eq!(stats.mean, ms(5));
+        // sqrt(20 / 3) ms
+        assert_eq!(stats.stddev.as_micros(), 2581);
+
+        let single = Stats::from_samples(&[ms(3)]);
+        assert_eq!(single.median, ms(3));
+        assert_eq!(single.stddev, Duration::from_secs(0));
+    }
+
+    #[test]
+    fn test_bench_day() {
+        let registry = solution::registry();
+        let three = NonZeroUsize::new(3).unwrap();
+        let report = bench_day(1, registry.get(1).unwrap(), "+1\n-2\n+3\n+1", three).unwrap();
+        assert_eq!(report.iterations, 3);
+        assert!(report.parse.min <= report.parse.median);
+        assert!(bench_day(1, registry.get(1).unwrap(), "+1\nx", three).is_err());
+    }
+}
@@ -1,34 +1,95 @@
-use super::utils;
+use crate::error::{Error, Result};
+use crate::solution::{Answer, Solution};
+use crate::utils;
 
 use std::collections::HashSet;
 
-fn parse_text(contents: &str) -> Vec<i32> {
-    contents.split("\n").map(|e| e.parse().unwrap()).collect()
+pub fn parse_text(contents: &str) -> Result<Vec<i32>> {
+    contents
+        .lines()
+        .enumerate()
+        .map(|(n, l)| (n, l, l.trim()))
+        .filter(|(_, _, e)| !e.is_empty())
+        .map(|(n, l, e)| {
+            e.parse().map_err(|_| {
+                Error::parse(
+                    n + 1,
+                    utils::column_of(l, e),
+                    format!("bad frequency change {:?}", e),
+                )
+            })
+        })
+        .collect()
 }
 
-pub fn run() {
-    let filename = "inputs/01/input.txt";
-    let contents: String = utils::read_input(&filename);
-    let entries = parse_text(&contents);
+/// Whether the running frequency ever repeats. After the first pass every total moves on by
+/// the net drift per pass, so when that is not zero two totals can only meet if they are
+/// congruent modulo the drift.
+fn repeats(entries: &[i32]) -> bool {
+    let drift: i64 = entries.iter().map(|&x| i64::from(x)).sum();
+    if drift == 0 {
+        return true;
+    }
+    let mut residues = HashSet::new();
+    let mut acc: i64 = 0;
+    !entries.iter().all(|&x| {
+        acc += i64::from(x);
+        residues.insert(acc.rem_euclid(drift))
+    })
+}
 
-    let mut cumulative: Vec<i32> = Vec::new();
+pub fn first_seen_twice(entries: &[i32]) -> Result<i32> {
+    if !repeats(entries) {
+        return Err(Error::NoAnswer(
+            "the frequency drifts away and never repeats".to_string(),
+        ));
+    }
     let mut acc: i32 = 0;
     let mut seen: HashSet<i32> = HashSet::new();
-    let mut seen_twice: Option<i32> = None;
+    seen.insert(acc);
 
     for x in entries.iter().cycle() {
-        acc = acc + x;
-        cumulative.push(acc);
-        if let None = seen_twice {
-            if seen.contains(&acc) {
-                println!("First seen twice: {:?}", acc);
-                seen_twice = Some(acc);
-            };
-        } else if cumulative.len() >= entries.len() {
+        acc += x;
+        if !seen.insert(acc) {
             break;
-        };
-        seen.insert(acc);
+        }
+    }
+    Ok(acc)
+}
+
+pub struct Day01;
+
+impl Solution for Day01 {
+    type Input = Vec<i32>;
+
+    fn parse(&self, text: &str) -> Result<Vec<i32>> {
+        parse_text(text)
     }
 
-    println!("End value: {:?}", cumulative[entries.len() - 1]);
+    fn part1(&self, entries: &Vec<i32>) -> Result<Answer> {
+        Ok(Answer::from(entries.iter().sum::<i32>()))
+    }
+
+    fn part2(&self, entries: &Vec<i32>) -> Result<Answer> {
+        Ok(Answer::from(first_seen_twice(entries)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_seen_twice() {
+        assert_eq!(first_seen_twice(&[1, -1]), Ok(0));
+        assert_eq!(first_seen_twice(&[3, 3, 4, -2, -4]), Ok(10));
+        assert_eq!(first_seen_twice(&[-6, 3, 8, 5, -6]), Ok(5));
+        assert_eq!(first_seen_twice(&[7, 7, -2, -7, -4]), Ok(14));
+        assert_eq!(first_seen_twice(&[]), Ok(0));
+        let drifting = Err(Error::NoAnswer(
+            "the frequency drifts away and never repeats".to_string(),
+        ));
+        assert_eq!(first_seen_twice(&[1, 1]), drifting);
+        assert_eq!(first_seen_twice(&[5, -2]), drifting);
+    }
 }
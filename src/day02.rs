@@ -1,10 +1,12 @@
+use crate::error::Result;
+use crate::solution::{Answer, Solution};
 use crate::utils;
 
-mod part1 {
+pub mod part1 {
     use std::collections::HashMap;
 
     pub fn count_letters(id: &str) -> HashMap<char, i32> {
-        id.chars().into_iter().fold(HashMap::new(), |mut acc, c| {
+        id.chars().fold(HashMap::new(), |mut acc, c| {
             *acc.entry(c).or_insert(0) += 1;
             acc
         })
@@ -14,7 +16,7 @@ mod part1 {
         hashmap.iter().filter(|(&_, &v)| v == n).count()
     }
 
-    pub fn checksum(entries: &Vec<String>) -> usize {
+    pub fn checksum(entries: &[String]) -> usize {
         let counts: Vec<HashMap<char, i32>> = entries.iter().map(|e| count_letters(e)).collect();
         let n2 = counts
             .iter()
@@ -28,7 +30,7 @@ mod part1 {
     }
 }
 
-mod part2 {
+pub mod part2 {
     use std::collections::HashMap;
 
     pub fn distance(str1: &str, str2: &str) -> usize {
@@ -46,7 +48,7 @@ mod part2 {
             .collect::<String>()
     }
 
-    pub fn get_closest_pair(entries: &Vec<String>) -> (String, String) {
+    pub fn get_closest_pair(entries: &[String]) -> (String, String) {
         let mut counts: HashMap<usize, (String, String)> = HashMap::new();
         for i in 0..entries.len() {
             for j in 0..entries.len() {
@@ -62,16 +64,25 @@ mod part2 {
     }
 }
 
-pub fn run() {
-    let filename = "inputs/02/input.txt";
-    let contents: String = utils::read_input(&filename);
-    let entries = utils::str_to_trimmed_lines_vec(&contents);
-    let closest = part2::get_closest_pair(&entries);
-    let in_common = part2::letters_in_common(&closest.0, &closest.1);
+pub struct Day02;
 
-    println!("Checksum: {:?}", part1::checksum(&entries));
-    println!("Closest: {:?}", closest);
-    println!("Chars in common: {:?}", in_common);
+impl Solution for Day02 {
+    type Input = Vec<String>;
+
+    fn parse(&self, text: &str) -> Result<Vec<String>> {
+        Ok(utils::str_to_trimmed_lines_vec(text))
+    }
+
+    fn part1(&self, entries: &Vec<String>) -> Result<Answer> {
+        Ok(Answer::from(part1::checksum(entries)))
+    }
+
+    fn part2(&self, entries: &Vec<String>) -> Result<Answer> {
+        let closest = part2::get_closest_pair(entries);
+        Ok(Answer::from(part2::letters_in_common(
+            &closest.0, &closest.1,
+        )))
+    }
 }
 
 #[cfg(test)]
@@ -1,15 +1,16 @@
-use crate::utils;
+use crate::error::{Error, Result};
+use crate::solution::{Answer, Solution};
 
-fn is_polar_pair(cs: &(char, char)) -> bool {
+pub fn is_polar_pair(cs: &(char, char)) -> bool {
     let (c1, c2) = cs;
     (*c1 != *c2) && (*c1 == c2.to_ascii_uppercase() || c1.to_ascii_uppercase() == *c2)
 }
 
-fn remove_polar_pairs(text: &str) -> String {
+pub fn remove_polar_pairs(text: &str) -> String {
     let chars: Vec<char> = text.chars().collect();
     let mut result = String::new();
     let mut i = 0usize;
-    while i < (text.len() - 1) {
+    while i + 1 < chars.len() {
         let pair = (chars[i], chars[i + 1]);
         if is_polar_pair(&pair) {
             i += 2;
@@ -18,11 +19,13 @@ fn remove_polar_pairs(text: &str) -> String {
             i += 1;
         }
     }
-    result.push(chars[i]);
+    if i < chars.len() {
+        result.push(chars[i]);
+    }
     result
 }
 
-fn remove_all_polar_pairs(text: &str) -> String {
+pub fn remove_all_polar_pairs(text: &str) -> String {
     let mut old_len = text.len();
     let mut curr = remove_polar_pairs(text);
     while curr.len() < old_len {
@@ -32,11 +35,50 @@ fn remove_all_polar_pairs(text: &str) -> String {
     curr
 }
 
-pub fn run() {
-    let filename = "inputs/05/input.txt";
-    let contents: String = utils::read_input(&filename).trim().to_string();
+pub fn shortest_without_one_unit(text: &str) -> usize {
+    (b'a'..=b'z')
+        .map(|unit| {
+            let stripped: String = text
+                .chars()
+                .filter(|c| c.to_ascii_lowercase() != unit as char)
+                .collect();
+            remove_all_polar_pairs(&stripped).len()
+        })
+        .min()
+        .unwrap()
+}
+
+pub struct Day05;
+
+impl Solution for Day05 {
+    type Input = String;
+
+    fn parse(&self, text: &str) -> Result<String> {
+        let polymer = text.trim();
+        let offset = text.len() - text.trim_start().len();
+        if let Some((i, c)) = polymer
+            .char_indices()
+            .find(|(_, c)| !c.is_ascii_alphabetic())
+        {
+            let before = &text[..offset + i];
+            let line = before.matches('\n').count() + 1;
+            let column = before.len() - before.rfind('\n').map_or(0, |n| n + 1) + 1;
+            return Err(Error::parse(
+                line,
+                column,
+                format!("unexpected unit {:?}", c),
+            ));
+        }
+        Ok(polymer.to_string())
+    }
+
+    fn part1(&self, polymer: &String) -> Result<Answer> {
+        Ok(Answer::from(remove_all_polar_pairs(polymer).len()))
+    }
 
-    println!("Final:\n{:?}", remove_polar_pairs(&contents).len());
+    fn part2(&self, polymer: &String) -> Result<Answer> {
+        Ok(Answer::from(shortest_without_one_unit(polymer)))
+    }
 }
 
 #[cfg(test)]
@@ -45,7 +87,7 @@ mod tests {
 
     #[test]
     fn test_polar_pair_elimination() {
-        assert_eq!(is_polar_pair(&('l', 'o')), false);
+        assert!(!is_polar_pair(&('l', 'o')));
         assert_eq!(remove_polar_pairs("lol"), "lol".to_string());
         assert_eq!(remove_polar_pairs("Llol"), "ol".to_string());
         assert_eq!(
@@ -56,5 +98,20 @@ mod tests {
             remove_all_polar_pairs("dabAcCaCBAcCcaDA"),
             "dabCBAcaDA".to_string()
         );
+        assert_eq!(remove_all_polar_pairs("abBA"), "".to_string());
+        assert_eq!(shortest_without_one_unit("dabAcCaCBAcCcaDA"), 4);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Day05.parse("  abBA\n"), Ok("abBA".to_string()));
+        assert_eq!(
+            Day05.parse("abc\nde1"),
+            Err(Error::parse(1, 4, "unexpected unit '\\n'"))
+        );
+        assert_eq!(
+            Day05.parse("abcde1"),
+            Err(Error::parse(1, 6, "unexpected unit '1'"))
+        );
     }
 }
@@ -1,6 +1,21 @@
+use crate::error::{Error, Result};
+use crate::solution::{Answer, Solution};
 use crate::utils;
 use std::collections::HashMap;
-use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fmt;
+use std::iter::Peekable;
+
+pub mod asm;
+pub mod compile;
+pub mod debugger;
+pub mod decompile;
+pub mod disasm;
+pub mod generate;
+pub mod halting;
+pub mod optimize;
+pub mod program;
+pub mod trace;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Opcode {
@@ -22,149 +37,313 @@ pub enum Opcode {
     Eqrr,
 }
 
+impl Opcode {
+    pub const ALL: [Opcode; 16] = [
+        Opcode::Addr,
+        Opcode::Addi,
+        Opcode::Mulr,
+        Opcode::Muli,
+        Opcode::Banr,
+        Opcode::Bani,
+        Opcode::Borr,
+        Opcode::Bori,
+        Opcode::Setr,
+        Opcode::Seti,
+        Opcode::Gtir,
+        Opcode::Gtri,
+        Opcode::Gtrr,
+        Opcode::Eqir,
+        Opcode::Eqri,
+        Opcode::Eqrr,
+    ];
+
+    /// The mnemonic used in program listings, e.g. `addr`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Opcode::Addr => "addr",
+            Opcode::Addi => "addi",
+            Opcode::Mulr => "mulr",
+            Opcode::Muli => "muli",
+            Opcode::Banr => "banr",
+            Opcode::Bani => "bani",
+            Opcode::Borr => "borr",
+            Opcode::Bori => "bori",
+            Opcode::Setr => "setr",
+            Opcode::Seti => "seti",
+            Opcode::Gtir => "gtir",
+            Opcode::Gtri => "gtri",
+            Opcode::Gtrr => "gtrr",
+            Opcode::Eqir => "eqir",
+            Opcode::Eqri => "eqri",
+            Opcode::Eqrr => "eqrr",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Opcode> {
+        Opcode::ALL.iter().cloned().find(|op| op.name() == name)
+    }
+
+    /// How inputs A and B are read. Output C is always a register.
+    pub fn inputs(self) -> (Operand, Operand) {
+        use Operand::*;
+        match self {
+            Opcode::Addr | Opcode::Mulr | Opcode::Banr | Opcode::Borr => (Register, Register),
+            Opcode::Addi | Opcode::Muli | Opcode::Bani | Opcode::Bori => (Register, Immediate),
+            Opcode::Setr => (Register, Unused),
+            Opcode::Seti => (Immediate, Unused),
+            Opcode::Gtir | Opcode::Eqir => (Immediate, Register),
+            Opcode::Gtri | Opcode::Eqri => (Register, Immediate),
+            Opcode::Gtrr | Opcode::Eqrr => (Register, Register),
+        }
+    }
+}
+
+/// How an instruction input is interpreted.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Operand {
+    Register,
+    Immediate,
+    Unused,
+}
+
+/// What `add*` and `mul*` do when a result does not fit in a register. The default reports
+/// the overflow as an error, so programs behave the same in debug and release builds.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Arithmetic {
+    #[default]
+    Checked,
+    Wrapping,
+    Saturating,
+}
+
+/// A register value: `usize` as in the puzzles, or `i64` for programs that need negative
+/// numbers. Immediates are always non-negative.
+pub trait Word: Copy + PartialOrd + Default + fmt::Debug + fmt::Display + 'static {
+    fn from_immediate(value: usize) -> Option<Self>;
+    fn from_bool(value: bool) -> Self;
+    /// The value as an instruction index, if it is not negative.
+    fn to_index(self) -> Option<usize>;
+    /// The instruction after one that left this value in the instruction pointer register:
+    /// the value plus one, or `usize::MAX`, past the end of any program, when that is
+    /// negative or does not fit.
+    fn next_index(self) -> usize {
+        self.add(Self::from_bool(true), Arithmetic::Checked)
+            .and_then(Self::to_index)
+            .unwrap_or(usize::MAX)
+    }
+    fn add(self, other: Self, arithmetic: Arithmetic) -> Option<Self>;
+    fn mul(self, other: Self, arithmetic: Arithmetic) -> Option<Self>;
+    fn bitand(self, other: Self) -> Self;
+    fn bitor(self, other: Self) -> Self;
+}
+
+macro_rules! word {
+    ($t:ty) => {
+        impl Word for $t {
+            fn from_immediate(value: usize) -> Option<Self> {
+                <$t>::try_from(value).ok()
+            }
+
+            fn from_bool(value: bool) -> Self {
+                value as $t
+            }
+
+            fn to_index(self) -> Option<usize> {
+                usize::try_from(self).ok()
+            }
+
+            fn add(self, other: Self, arithmetic: Arithmetic) -> Option<Self> {
+                match arithmetic {
+                    Arithmetic::Checked => self.checked_add(other),
+                    Arithmetic::Wrapping => Some(self.wrapping_add(other)),
+                    Arithmetic::Saturating => Some(self.saturating_add(other)),
+                }
+            }
+
+            fn mul(self, other: Self, arithmetic: Arithmetic) -> Option<Self> {
+                match arithmetic {
+                    Arithmetic::Checked => self.checked_mul(other),
+                    Arithmetic::Wrapping => Some(self.wrapping_mul(other)),
+                    Arithmetic::Saturating => Some(self.saturating_mul(other)),
+                }
+            }
+
+            fn bitand(self, other: Self) -> Self {
+                self & other
+            }
+
+            fn bitor(self, other: Self) -> Self {
+                self | other
+            }
+        }
+    };
+}
+
+word!(usize);
+word!(i64);
+
+/// A register machine with `N` registers holding `W` values. The opcode samples use four
+/// registers, `#ip` programs use six.
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Copy, Clone, PartialEq)]
-pub struct CPU {
-    r: [usize; 4],
+pub struct CPU<const N: usize = 4, W: Word = usize> {
+    r: [W; N],
+    arithmetic: Arithmetic,
 }
 
 impl CPU {
-    pub fn from_vec(rs: &Vec<usize>) -> Self {
-        if rs.len() != 4 {
-            panic!("CPUs must have exactly 4 registers");
+    pub fn from_vec(rs: &[usize]) -> Result<Self> {
+        CPU::from_slice(rs)
+    }
+}
+
+impl<const N: usize, W: Word> CPU<N, W> {
+    pub fn new(r: [W; N]) -> Self {
+        CPU {
+            r,
+            arithmetic: Arithmetic::default(),
+        }
+    }
+
+    pub fn from_slice(rs: &[W]) -> Result<Self> {
+        if rs.len() != N {
+            return Err(Error::InvalidProgram(format!(
+                "CPUs must have exactly {} registers, got {}",
+                N,
+                rs.len()
+            )));
         }
-        let array: [usize; 4] = [rs[0], rs[1], rs[2], rs[3]];
-        CPU { r: array }
+        let mut r = [W::default(); N];
+        r.copy_from_slice(rs);
+        Ok(CPU::new(r))
+    }
+
+    pub fn with_arithmetic(mut self, arithmetic: Arithmetic) -> Self {
+        self.arithmetic = arithmetic;
+        self
+    }
+
+    pub fn arithmetic(&self) -> Arithmetic {
+        self.arithmetic
+    }
+
+    pub fn registers(&self) -> &[W] {
+        &self.r
+    }
+
+    pub fn registers_mut(&mut self) -> &mut [W] {
+        &mut self.r
+    }
+
+    fn reg(&self, i: usize) -> Result<W> {
+        self.r.get(i).cloned().ok_or_else(|| {
+            Error::InvalidProgram(format!(
+                "register {} is out of range for a CPU with {} registers",
+                i, N
+            ))
+        })
+    }
+
+    fn imm(&self, value: usize) -> Result<W> {
+        W::from_immediate(value).ok_or_else(|| {
+            Error::InvalidProgram(format!("immediate {} does not fit in a register", value))
+        })
+    }
+
+    fn store(mut self, c: usize, value: W) -> Result<CPU<N, W>> {
+        self.reg(c)?;
+        self.r[c] = value;
+        Ok(self)
+    }
+
+    fn sum(&self, a: W, b: W) -> Result<W> {
+        a.add(b, self.arithmetic)
+            .ok_or_else(|| Error::InvalidProgram(format!("{} + {} overflows", a, b)))
+    }
+
+    fn product(&self, a: W, b: W) -> Result<W> {
+        a.mul(b, self.arithmetic)
+            .ok_or_else(|| Error::InvalidProgram(format!("{} * {} overflows", a, b)))
     }
 
     // addr (add register) stores into register C the result of adding register A and register B.
-    pub fn addr(self, inst: Instruction) -> CPU {
-        let mut next = self.clone();
-        next.r[inst.c] = next.r[inst.a] + next.r[inst.b];
-        next
+    pub fn addr(self, inst: Instruction) -> Result<CPU<N, W>> {
+        self.store(inst.c, self.sum(self.reg(inst.a)?, self.reg(inst.b)?)?)
     }
     // addi (add immediate) stores into register C the result of adding register A and value B.
-    pub fn addi(self, inst: Instruction) -> CPU {
-        let mut next = self.clone();
-        next.r[inst.c] = next.r[inst.a] + inst.b;
-        next
+    pub fn addi(self, inst: Instruction) -> Result<CPU<N, W>> {
+        self.store(inst.c, self.sum(self.reg(inst.a)?, self.imm(inst.b)?)?)
     }
     // mulr (multiply register) stores into register C the result of multiplying register A and
     // register B.
-    pub fn mulr(self, inst: Instruction) -> CPU {
-        let mut next = self.clone();
-        next.r[inst.c] = next.r[inst.a] * next.r[inst.b];
-        next
+    pub fn mulr(self, inst: Instruction) -> Result<CPU<N, W>> {
+        self.store(inst.c, self.product(self.reg(inst.a)?, self.reg(inst.b)?)?)
     }
     // muli (multiply immediate) stores into register C the result of multiplying register A and
     // value B.
-    pub fn muli(self, inst: Instruction) -> CPU {
-        let mut next = self.clone();
-        next.r[inst.c] = next.r[inst.a] * inst.b;
-        next
+    pub fn muli(self, inst: Instruction) -> Result<CPU<N, W>> {
+        self.store(inst.c, self.product(self.reg(inst.a)?, self.imm(inst.b)?)?)
     }
     // banr (bitwise AND register) stores into register C the result of the bitwise AND of register
     // A and register B.
-    pub fn banr(self, inst: Instruction) -> CPU {
-        let mut next = self.clone();
-        next.r[inst.c] = next.r[inst.a] & next.r[inst.b];
-        next
+    pub fn banr(self, inst: Instruction) -> Result<CPU<N, W>> {
+        self.store(inst.c, self.reg(inst.a)?.bitand(self.reg(inst.b)?))
     }
     // bani (bitwise AND immediate) stores into register C the result of the bitwise AND of register
     // A and value B.
-    pub fn bani(self, inst: Instruction) -> CPU {
-        let mut next = self.clone();
-        next.r[inst.c] = next.r[inst.a] & inst.b;
-        next
+    pub fn bani(self, inst: Instruction) -> Result<CPU<N, W>> {
+        self.store(inst.c, self.reg(inst.a)?.bitand(self.imm(inst.b)?))
     }
     // borr (bitwise OR register) stores into register C the result of the bitwise OR of register A
     // and register B.
-    pub fn borr(self, inst: Instruction) -> CPU {
-        let mut next = self.clone();
-        next.r[inst.c] = next.r[inst.a] | next.r[inst.b];
-        next
+    pub fn borr(self, inst: Instruction) -> Result<CPU<N, W>> {
+        self.store(inst.c, self.reg(inst.a)?.bitor(self.reg(inst.b)?))
     }
     // bori (bitwise OR immediate) stores into register C the result of the bitwise OR of register A
     // and value B.
-    pub fn bori(self, inst: Instruction) -> CPU {
-        let mut next = self.clone();
-        next.r[inst.c] = next.r[inst.a] | inst.b;
-        next
+    pub fn bori(self, inst: Instruction) -> Result<CPU<N, W>> {
+        self.store(inst.c, self.reg(inst.a)?.bitor(self.imm(inst.b)?))
     }
     // setr (set register) copies the contents of register A into register C. (Input B is ignored.)
-    pub fn setr(self, inst: Instruction) -> CPU {
-        let mut next = self.clone();
-        next.r[inst.c] = next.r[inst.a];
-        next
+    pub fn setr(self, inst: Instruction) -> Result<CPU<N, W>> {
+        self.store(inst.c, self.reg(inst.a)?)
     }
     // seti (set immediate) stores value A into register C. (Input B is ignored.)
-    pub fn seti(self, inst: Instruction) -> CPU {
-        let mut next = self.clone();
-        next.r[inst.c] = inst.a;
-        next
+    pub fn seti(self, inst: Instruction) -> Result<CPU<N, W>> {
+        self.store(inst.c, self.imm(inst.a)?)
     }
     // gtir (greater-than immediate/register) sets register C to 1 if value A is greater than
     // register B. Otherwise, register C is set to 0.
-    pub fn gtir(self, inst: Instruction) -> CPU {
-        let mut next = self.clone();
-        next.r[inst.c] = match inst.a > next.r[inst.b] {
-            true => 1,
-            false => 0,
-        };
-        next
+    pub fn gtir(self, inst: Instruction) -> Result<CPU<N, W>> {
+        self.store(inst.c, W::from_bool(self.imm(inst.a)? > self.reg(inst.b)?))
     }
     // gtri (greater-than register/immediate) sets register C to 1 if register A is greater than
     // value B. Otherwise, register C is set to 0.
-    pub fn gtri(self, inst: Instruction) -> CPU {
-        let mut next = self.clone();
-        next.r[inst.c] = match next.r[inst.a] > inst.b {
-            true => 1,
-            false => 0,
-        };
-        next
+    pub fn gtri(self, inst: Instruction) -> Result<CPU<N, W>> {
+        self.store(inst.c, W::from_bool(self.reg(inst.a)? > self.imm(inst.b)?))
     }
     // gtrr (greater-than register/register) sets register C to 1 if register A is greater than
     // register B. Otherwise, register C is set to 0.
-    pub fn gtrr(self, inst: Instruction) -> CPU {
-        let mut next = self.clone();
-        next.r[inst.c] = match next.r[inst.a] > next.r[inst.b] {
-            true => 1,
-            false => 0,
-        };
-        next
+    pub fn gtrr(self, inst: Instruction) -> Result<CPU<N, W>> {
+        self.store(inst.c, W::from_bool(self.reg(inst.a)? > self.reg(inst.b)?))
     }
     // eqir (equal immediate/register) sets register C to 1 if value A is equal to register B.
     // Otherwise, register C is set to 0.
-    pub fn eqir(self, inst: Instruction) -> CPU {
-        let mut next = self.clone();
-        next.r[inst.c] = match inst.a == next.r[inst.b] {
-            true => 1,
-            false => 0,
-        };
-        next
+    pub fn eqir(self, inst: Instruction) -> Result<CPU<N, W>> {
+        self.store(inst.c, W::from_bool(self.imm(inst.a)? == self.reg(inst.b)?))
     }
     // eqri (equal register/immediate) sets register C to 1 if register A is equal to value B.
     // Otherwise, register C is set to 0.
-    pub fn eqri(self, inst: Instruction) -> CPU {
-        let mut next = self.clone();
-        next.r[inst.c] = match next.r[inst.a] == inst.b {
-            true => 1,
-            false => 0,
-        };
-        next
+    pub fn eqri(self, inst: Instruction) -> Result<CPU<N, W>> {
+        self.store(inst.c, W::from_bool(self.reg(inst.a)? == self.imm(inst.b)?))
     }
     // eqrr (equal register/register) sets register C to 1 if register A is equal to register B.
     // Otherwise, register C is set to 0.
-    pub fn eqrr(self, inst: Instruction) -> CPU {
-        let mut next = self.clone();
-        next.r[inst.c] = match next.r[inst.a] == next.r[inst.b] {
-            true => 1,
-            false => 0,
-        };
-        next
+    pub fn eqrr(self, inst: Instruction) -> Result<CPU<N, W>> {
+        self.store(inst.c, W::from_bool(self.reg(inst.a)? == self.reg(inst.b)?))
     }
 
-    pub fn execute(self, inst: Instruction) -> CPU {
-        // do nothing
+    pub fn execute(self, inst: Instruction) -> Result<CPU<N, W>> {
         match inst.opcode {
             Opcode::Addr => self.addr(inst),
             Opcode::Addi => self.addi(inst),
@@ -186,45 +365,137 @@ impl CPU {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Instruction {
-    opcode: Opcode,
-    a: usize,
-    b: usize,
-    c: usize,
+    pub opcode: Opcode,
+    pub a: usize,
+    pub b: usize,
+    pub c: usize,
+}
+
+/// Formats as `opcode A B C`, the form programs are written in.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {} {}", self.opcode.name(), self.a, self.b, self.c)
+    }
 }
 
-type Fixture = (CPU, Vec<usize>, CPU);
+pub type Fixture = (CPU, Vec<usize>, CPU);
+
+/// Parses whitespace- or comma-separated numbers out of `part`, a slice of `line` (line `n`).
+fn numbers(line: &str, part: &str, n: usize) -> Result<Vec<usize>> {
+    part.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|x| !x.is_empty())
+        .map(|x| {
+            x.parse::<usize>().map_err(|_| {
+                Error::parse(n, utils::column_of(line, x), format!("bad number {:?}", x))
+            })
+        })
+        .collect()
+}
 
-pub fn parse_fixtures(text: &str) -> Vec<Fixture> {
+/// Like `numbers`, requiring exactly `expected` of them.
+fn parse_numbers(line: &str, part: &str, n: usize, expected: usize) -> Result<Vec<usize>> {
+    let numbers = numbers(line, part, n)?;
+    if numbers.len() != expected {
+        return Err(Error::parse(
+            n,
+            utils::column_of(line, part),
+            format!("expected {} numbers, found {}", expected, numbers.len()),
+        ));
+    }
+    Ok(numbers)
+}
+
+/// Parses `Before: [3, 2, 1, 1]` or `After:  [3, 2, 2, 1]`, with any spacing and any number
+/// of digits per register.
+fn parse_registers(line: &str, n: usize, label: &str) -> Result<CPU> {
+    let trimmed = line.trim();
+    let rest = trimmed
+        .strip_prefix(label)
+        .ok_or_else(|| {
+            Error::parse(
+                n,
+                utils::column_of(line, trimmed),
+                format!("expected {:?}", label),
+            )
+        })?
+        .trim_start();
+    let registers = rest
+        .strip_prefix('[')
+        .and_then(|inner| inner.strip_suffix(']'))
+        .unwrap_or(rest);
+    let values = numbers(line, registers, n)?;
+    if registers.len() == rest.len() || values.len() != 4 {
+        return Err(Error::parse(
+            n,
+            utils::column_of(line, registers),
+            "expected four registers in brackets",
+        ));
+    }
+    CPU::from_vec(&values)
+}
+
+/// The non-blank lines of `text` with their 1-based line numbers. `str::lines` also drops the
+/// `\r` of CRLF line endings.
+fn content_lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line))
+        .filter(|(_, line)| !line.trim().is_empty())
+}
+
+fn is_sample_start(line: &str) -> bool {
+    line.trim_start().starts_with("Before:")
+}
+
+/// Parses samples from `lines` for as long as the next line starts one.
+fn parse_samples<'a, I>(lines: &mut Peekable<I>) -> Result<Vec<Fixture>>
+where
+    I: Iterator<Item = (usize, &'a str)>,
+{
     let mut fixtures = vec![];
-    for case in text.split("\n\n") {
-        let lines: Vec<&str> = case.split("\n").collect();
+    while let Some((first, line)) = lines.next_if(|&(_, line)| is_sample_start(line)) {
+        let before = parse_registers(line, first, "Before:")?;
+        let mut last = first;
+        let mut next = || match lines.next_if(|&(_, line)| !is_sample_start(line)) {
+            Some((n, line)) => {
+                last = n;
+                Ok((n, line))
+            }
+            None => Err(Error::parse(
+                last,
+                1,
+                "sample must have Before, instruction and After lines",
+            )),
+        };
+        let (n, line) = next()?;
+        let instruction = parse_numbers(line, line, n, 4)?;
+        let (n, line) = next()?;
+        let after = parse_registers(line, n, "After:")?;
+        fixtures.push((before, instruction, after));
+    }
+    Ok(fixtures)
+}
 
-        let before: Vec<usize> = lines[0][9..19]
-            .split(", ")
-            .map(|x| x.parse::<usize>().expect("Bad before case"))
-            .collect();
-        let instructions: Vec<usize> = lines[1]
-            .split(" ")
-            .map(|x| x.parse::<usize>().expect("Bad instructions"))
-            .collect();
-        let after: Vec<usize> = lines[2][9..19]
-            .split(", ")
-            .map(|x| x.parse::<usize>().expect("Bad after case"))
-            .collect();
-        fixtures.push((CPU::from_vec(&before), instructions, CPU::from_vec(&after)));
+/// Parses samples alone. Blank lines between and around them are ignored.
+pub fn parse_fixtures(text: &str) -> Result<Vec<Fixture>> {
+    let mut lines = content_lines(text).peekable();
+    let fixtures = parse_samples(&mut lines)?;
+    match lines.next() {
+        Some((n, line)) => Err(Error::parse(
+            n,
+            utils::column_of(line, line.trim()),
+            "expected \"Before:\"",
+        )),
+        None => Ok(fixtures),
     }
-    fixtures
 }
 
-pub fn parse_program(text: &str) -> Vec<Vec<usize>> {
-    let flat_codes: Vec<usize> = text
-        .split_whitespace()
-        .map(|c| c.parse::<usize>().unwrap())
-        .collect();
-    let groups4: Vec<_> = flat_codes.chunks_exact(4).map(|x| x.to_vec()).collect();
-    groups4
+pub fn parse_program(text: &str) -> Result<Vec<Vec<usize>>> {
+    content_lines(text)
+        .map(|(n, line)| parse_numbers(line, line, n, 4))
+        .collect()
 }
 
 pub fn which_opcodes_work(fixture: Fixture, possibilities: Vec<Opcode>) -> Vec<Opcode> {
@@ -234,117 +505,220 @@ pub fn which_opcodes_work(fixture: Fixture, possibilities: Vec<Opcode>) -> Vec<O
         .into_iter()
         .filter(|&opcode| {
             let instruction = Instruction { opcode, a, b, c };
-            before.execute(instruction) == after
+            before.execute(instruction) == Ok(after)
         })
         .collect()
 }
 
-pub fn narrow_opcodes_to_known(fixtures: Vec<Fixture>) -> HashMap<usize, Opcode> {
-    let all_opcodes = vec![
-        Opcode::Addr,
-        Opcode::Addi,
-        Opcode::Mulr,
-        Opcode::Muli,
-        Opcode::Banr,
-        Opcode::Bani,
-        Opcode::Borr,
-        Opcode::Bori,
-        Opcode::Setr,
-        Opcode::Seti,
-        Opcode::Gtir,
-        Opcode::Gtri,
-        Opcode::Gtrr,
-        Opcode::Eqir,
-        Opcode::Eqri,
-        Opcode::Eqrr,
-    ];
-    let mut mapping: HashMap<usize, Vec<Opcode>> = HashMap::new();
-    for fixture in fixtures.clone() {
-        let i = fixture.1[0];
-        let curr = mapping.entry(i).or_insert(all_opcodes.clone());
-        let next = which_opcodes_work(fixture.clone(), curr.to_vec());
-        mapping.insert(i, next);
-    }
-    println!("mapping: {:?}", mapping);
-
-    let mut known: HashMap<usize, Opcode> = HashMap::new();
-    loop {
-        for (k, v) in mapping.clone().drain() {
-            if v.len() == 1 {
-                known.insert(k, v[0]);
-                println!("eliminating {:?}={:}", v[0], k);
-                for j in 0usize..16 {
-                    let others = mapping.get(&j).unwrap().to_owned();
-                    let winnowed = others.into_iter().filter(|&op| op != v[0]).collect();
-                    mapping.insert(j, winnowed);
+/// The opcodes each number could stand for, judging by its samples alone. Numbers without
+/// samples are left out.
+pub fn candidates(fixtures: &[Fixture]) -> HashMap<usize, Vec<Opcode>> {
+    let mut candidates: HashMap<usize, Vec<Opcode>> = HashMap::new();
+    for fixture in fixtures {
+        let number = fixture.1[0];
+        let current = candidates
+            .remove(&number)
+            .unwrap_or_else(|| Opcode::ALL.to_vec());
+        candidates.insert(number, which_opcodes_work(fixture.clone(), current));
+    }
+    candidates
+}
+
+/// Tries to give `number` an opcode in a matching of numbers to distinct opcodes, moving
+/// other numbers to alternative opcodes as needed (Kuhn's augmenting paths).
+fn augment(
+    number: usize,
+    options: &HashMap<usize, Vec<Opcode>>,
+    owner: &mut HashMap<Opcode, usize>,
+    seen: &mut Vec<Opcode>,
+) -> bool {
+    for &opcode in &options[&number] {
+        if seen.contains(&opcode) {
+            continue;
+        }
+        seen.push(opcode);
+        let free = match owner.get(&opcode) {
+            None => true,
+            Some(&other) => augment(other, options, owner, seen),
+        };
+        if free {
+            owner.insert(opcode, number);
+            return true;
+        }
+    }
+    false
+}
+
+/// Whether every number can be given its own opcode.
+fn has_matching(options: &HashMap<usize, Vec<Opcode>>) -> bool {
+    let mut owner = HashMap::new();
+    options
+        .keys()
+        .all(|&number| augment(number, options, &mut owner, &mut vec![]))
+}
+
+/// Narrows the candidates to the opcodes each number has in at least one consistent
+/// mapping, where every number stands for a different opcode. Numbers 0 to 15 without
+/// samples take part too, so elimination can still pin them down.
+pub fn consistent_candidates(fixtures: &[Fixture]) -> Result<HashMap<usize, Vec<Opcode>>> {
+    let mut options = candidates(fixtures);
+    for number in 0..Opcode::ALL.len() {
+        options
+            .entry(number)
+            .or_insert_with(|| Opcode::ALL.to_vec());
+    }
+    if !has_matching(&options) {
+        let mut numbers: Vec<usize> = options.keys().cloned().collect();
+        numbers.sort_unstable();
+        let empty: Vec<String> = numbers
+            .iter()
+            .filter(|n| options[n].is_empty())
+            .map(|n| n.to_string())
+            .collect();
+        return Err(Error::InvalidProgram(if empty.is_empty() {
+            "the samples cannot be satisfied with a different opcode for every number".to_string()
+        } else {
+            format!("no opcode matches every sample for {}", empty.join(", "))
+        }));
+    }
+    let mut pruned = HashMap::new();
+    for (&number, opcodes) in &options {
+        let possible = opcodes
+            .iter()
+            .cloned()
+            .filter(|&opcode| {
+                let mut fixed = options.clone();
+                for (&other, others) in fixed.iter_mut() {
+                    if other == number {
+                        *others = vec![opcode];
+                    } else {
+                        others.retain(|&op| op != opcode);
+                    }
                 }
-            }
+                has_matching(&fixed)
+            })
+            .collect();
+        pruned.insert(number, possible);
+    }
+    Ok(pruned)
+}
+
+/// Every mapping from numbers to distinct opcodes that agrees with the samples, up to `limit`
+/// of them.
+pub fn all_mappings(fixtures: &[Fixture], limit: usize) -> Result<Vec<HashMap<usize, Opcode>>> {
+    fn search(
+        numbers: &[usize],
+        options: &HashMap<usize, Vec<Opcode>>,
+        mapping: &mut HashMap<usize, Opcode>,
+        found: &mut Vec<HashMap<usize, Opcode>>,
+        limit: usize,
+    ) {
+        if found.len() >= limit {
+            return;
         }
-        println!("known: {:?}", known);
-        if known.len() == 16 {
-            break;
+        let (&number, rest) = match numbers.split_first() {
+            Some(split) => split,
+            None => {
+                found.push(mapping.clone());
+                return;
+            }
+        };
+        for &opcode in &options[&number] {
+            if mapping.values().any(|&op| op == opcode) {
+                continue;
+            }
+            mapping.insert(number, opcode);
+            search(rest, options, mapping, found, limit);
+            mapping.remove(&number);
         }
     }
-    known
+
+    let options = consistent_candidates(fixtures)?;
+    // Most constrained numbers first keeps the search shallow.
+    let mut numbers: Vec<usize> = options.keys().cloned().collect();
+    numbers.sort_by_key(|n| (options[n].len(), *n));
+    let mut found = vec![];
+    search(&numbers, &options, &mut HashMap::new(), &mut found, limit);
+    Ok(found)
 }
 
-pub fn part1(fixtures: Vec<Fixture>) {
-    let all_opcodes = vec![
-        Opcode::Addr,
-        Opcode::Addi,
-        Opcode::Mulr,
-        Opcode::Muli,
-        Opcode::Banr,
-        Opcode::Bani,
-        Opcode::Borr,
-        Opcode::Bori,
-        Opcode::Setr,
-        Opcode::Seti,
-        Opcode::Gtir,
-        Opcode::Gtri,
-        Opcode::Gtrr,
-        Opcode::Eqir,
-        Opcode::Eqri,
-        Opcode::Eqrr,
-    ];
-    let counts = fixtures
-        .into_iter()
-        .map(|f| which_opcodes_work(f, all_opcodes.clone()).len())
+/// The one mapping from all sixteen numbers to opcodes the samples allow, or an error listing
+/// the numbers that are still ambiguous and what they could be.
+pub fn infer_opcodes(fixtures: &[Fixture]) -> Result<HashMap<usize, Opcode>> {
+    let options = consistent_candidates(fixtures)?;
+    let mut unresolved: Vec<(&usize, &Vec<Opcode>)> =
+        options.iter().filter(|(_, ops)| ops.len() > 1).collect();
+    if !unresolved.is_empty() {
+        unresolved.sort_by_key(|(n, _)| **n);
+        let details: Vec<String> = unresolved
+            .iter()
+            .map(|(n, ops)| {
+                let names: Vec<&str> = ops.iter().map(|op| op.name()).collect();
+                format!("{} could be {}", n, names.join("/"))
+            })
+            .collect();
+        return Err(Error::InvalidProgram(format!(
+            "the samples do not pin down every opcode: {}",
+            details.join(", ")
+        )));
+    }
+    Ok(options.into_iter().map(|(n, ops)| (n, ops[0])).collect())
+}
+
+pub fn part1(fixtures: &[Fixture]) -> usize {
+    let all_opcodes = Opcode::ALL.to_vec();
+    fixtures
+        .iter()
+        .map(|f| which_opcodes_work(f.clone(), all_opcodes.clone()).len())
         .filter(|n| *n >= 3)
-        .count();
-    println!("part 1: {:?}", counts);
+        .count()
 }
 
-pub fn part2(fixtures: Vec<Fixture>, program: Vec<Vec<usize>>) {
-    let known = narrow_opcodes_to_known(fixtures.clone());
-    let mut cpu = CPU::from_vec(&vec![0, 0, 0, 0]);
-    println!("{:}: {:?}", 0, cpu);
+pub fn part2(fixtures: &[Fixture], program: &[Vec<usize>]) -> Result<CPU> {
+    let program = test_program(fixtures, program)?;
+    let mut machine: program::Machine<4> = program::Machine::new(&program)?;
+    machine.run(&program)?;
+    Ok(machine.cpu)
+}
 
-    let mut i = 0;
-    for line in program {
-        i += 1;
-        println!("{:?}", line);
-        let (&opcode, a, b, c) = (known.get(&line[0]).unwrap(), line[1], line[2], line[3]);
-        let instruction = Instruction { opcode, a, b, c };
-        println!("{:}: {:?}", i, instruction);
-        cpu = cpu.execute(instruction);
-        println!("{:}: {:?}", i, cpu)
-    }
+/// The test program with opcode numbers resolved from the samples.
+pub fn test_program(fixtures: &[Fixture], program: &[Vec<usize>]) -> Result<program::Program> {
+    let known = infer_opcodes(fixtures)?;
+    Ok(program::Program {
+        ip: None,
+        instructions: disasm::decode(program, &known)?,
+    })
+}
+
+/// The test program as a readable listing, with opcode numbers resolved from the samples.
+pub fn listing(fixtures: &[Fixture], program: &[Vec<usize>]) -> Result<String> {
+    let program = test_program(fixtures, program)?;
+    Ok(disasm::disassemble(&program.instructions, None))
 }
 
-pub fn run() {
-    let filename = "inputs/16/input.txt";
-    let contents: String = utils::read_input(&filename);
+pub struct Day16;
+
+impl Solution for Day16 {
+    type Input = (Vec<Fixture>, Vec<Vec<usize>>);
 
-    let split: Vec<&str> = contents.split("\n\n\n").collect();
+    /// Samples, then the test program from the first line that does not start a sample.
+    /// Blank lines, so the exact separation between the two, do not matter.
+    fn parse(&self, text: &str) -> Result<Self::Input> {
+        let mut lines = content_lines(text).peekable();
+        let fixtures = parse_samples(&mut lines)?;
+        let program = lines
+            .map(|(n, line)| parse_numbers(line, line, n, 4))
+            .collect::<Result<_>>()?;
+        Ok((fixtures, program))
+    }
 
-    // part 1
-    let fixtures = parse_fixtures(split[0]);
-    part1(fixtures.clone());
+    fn part1(&self, input: &Self::Input) -> Result<Answer> {
+        Ok(Answer::from(part1(&input.0)))
+    }
 
-    // part 2
-    let program = parse_program(split[1]);
-    part2(fixtures, program);
+    fn part2(&self, input: &Self::Input) -> Result<Answer> {
+        Ok(Answer::from(part2(&input.0, &input.1)?.r[0]))
+    }
 }
 
 #[cfg(test)]
@@ -353,218 +727,575 @@ mod tests {
 
     #[test]
     fn test_part1() {
-        let fixtures = parse_fixtures("Before: [3, 2, 1, 1]\n9 2 1 2\nAfter:  [3, 2, 2, 1]");
+        let fixtures =
+            parse_fixtures("Before: [3, 2, 1, 1]\n9 2 1 2\nAfter:  [3, 2, 2, 1]").unwrap();
         assert_eq!(fixtures.len(), 1);
         assert_eq!(fixtures[0].0.r[0], 3);
         assert_eq!(fixtures[0].1[0], 9);
         assert_eq!(fixtures[0].2.r[0], 3);
     }
 
+    #[test]
+    fn test_parse_errors() {
+        let text = "Before: [3, 2, 1, 1]\n9 2 1 2\nAfter:  [3, 2, 2, 1]\n\nBefore: [3, 2, 1, 1]\n9 x 1 2\nAfter:  [3, 2, 2, 1]";
+        assert_eq!(
+            parse_fixtures(text).unwrap_err(),
+            Error::parse(6, 3, "bad number \"x\"")
+        );
+        assert_eq!(
+            parse_fixtures("Before: [3, 2]\n9 2 1 2\nAfter:  [3, 2, 2, 1]").unwrap_err(),
+            Error::parse(1, 10, "expected four registers in brackets")
+        );
+        assert_eq!(
+            parse_program("1 2 3 4\n\n1 2 3").unwrap_err(),
+            Error::parse(3, 1, "expected 4 numbers, found 3")
+        );
+        assert!(CPU::from_vec(&[1, 2, 3]).is_err());
+    }
+
+    #[test]
+    fn test_parse_layout() {
+        let expected = Day16
+            .parse("Before: [3, 2, 1, 1]\n9 2 1 2\nAfter:  [3, 2, 2, 1]\n\n\n\n1 2 3 4\n5 6 7 8")
+            .unwrap();
+        let crlf = "Before: [3, 2, 1, 1]\r\n9 2 1 2\r\nAfter:  [3, 2, 2, 1]\r\n\r\n\r\n\r\n1 2 3 4\r\n5 6 7 8\r\n";
+        assert_eq!(Day16.parse(crlf).unwrap(), expected);
+        let loose = "\n  Before:[3,2,  1,1] \n\t9  2 1 2\nAfter: [ 3, 2, 2, 1 ]\n\n1 2 3 4\n\n5 6 7 8\n\n\n";
+        assert_eq!(Day16.parse(loose).unwrap(), expected);
+        // No program at all, as in a file of samples only.
+        let (fixtures, program) = Day16.parse(&crlf[..crlf.find("1 2 3 4").unwrap()]).unwrap();
+        assert_eq!((fixtures, program), (expected.0, vec![]));
+
+        let (fixtures, _) = Day16
+            .parse("Before: [10, 200, 3000, 4]\n12 0 1 3\nAfter:  [10, 200, 3000, 210]")
+            .unwrap();
+        assert_eq!(fixtures[0].0.r, [10, 200, 3000, 4]);
+        assert_eq!(fixtures[0].1, vec![12, 0, 1, 3]);
+        assert_eq!(fixtures[0].2.r, [10, 200, 3000, 210]);
+    }
+
+    #[test]
+    fn test_parse_error_lines() {
+        let sample = "Before: [3, 2, 1, 1]\r\n9 2 1 2\r\nAfter:  [3, 2, 2, 1]\r\n\r\n";
+        let truncated = format!("{}{}Before: [3, 2, 1, 1]\r\n9 2 1 2\r\n", sample, sample);
+        assert_eq!(
+            Day16.parse(&truncated).unwrap_err(),
+            Error::parse(
+                10,
+                1,
+                "sample must have Before, instruction and After lines"
+            )
+        );
+        let missing_after = format!(
+            "{}Before: [3, 2, 1, 1]\n9 2 1 2\n\nBefore: [0, 0, 0, 0]",
+            sample
+        );
+        assert_eq!(
+            parse_fixtures(&missing_after).unwrap_err(),
+            Error::parse(6, 1, "sample must have Before, instruction and After lines")
+        );
+        assert_eq!(
+            parse_fixtures(&format!(
+                "{}Before: [3, 2, 1, 1]\n9 2 1 2\nAfter:  3, 2, 2, 1",
+                sample
+            ))
+            .unwrap_err(),
+            Error::parse(7, 9, "expected four registers in brackets")
+        );
+        assert_eq!(
+            parse_fixtures(&format!("{}\n9 2 1 2", sample)).unwrap_err(),
+            Error::parse(6, 1, "expected \"Before:\"")
+        );
+        assert_eq!(
+            Day16
+                .parse(&format!("{}\n\n\n1 2 3 4\n\n1 2 x 4", sample))
+                .unwrap_err(),
+            Error::parse(10, 5, "bad number \"x\"")
+        );
+    }
+
+    fn sample(before: [usize; 4], instruction: [usize; 4], after: [usize; 4]) -> Fixture {
+        (CPU::new(before), instruction.to_vec(), CPU::new(after))
+    }
+
+    #[test]
+    fn test_infer_opcodes() {
+        // Only seti turns [0, 0, 0, 0] into [0, 5, 0, 0] with operands 5 0 1.
+        let seti = sample([0, 0, 0, 0], [7, 5, 0, 1], [0, 5, 0, 0]);
+        let ambiguous = sample([0, 0, 0, 0], [3, 1, 0, 2], [0, 0, 1, 0]);
+        let fixtures = vec![seti.clone(), ambiguous.clone()];
+        assert_eq!(
+            candidates(&fixtures)[&3],
+            vec![Opcode::Seti, Opcode::Gtir, Opcode::Eqri, Opcode::Eqrr]
+        );
+        // Every number without samples could be anything but seti.
+        let options = consistent_candidates(&fixtures).unwrap();
+        assert_eq!(options.len(), 16);
+        assert_eq!(options[&3], vec![Opcode::Gtir, Opcode::Eqri, Opcode::Eqrr]);
+        assert_eq!(options[&0].len(), 15);
+        let message = infer_opcodes(&fixtures).unwrap_err().to_string();
+        assert!(message.contains("the samples do not pin down every opcode: 0 could be addr/"));
+        assert!(message.contains(", 3 could be gtir/eqri/eqrr, "));
+        assert_eq!(all_mappings(&fixtures, 10).unwrap().len(), 10);
+        assert_eq!(all_mappings(&fixtures, 2).unwrap().len(), 2);
+
+        let fixtures = vec![
+            seti.clone(),
+            ambiguous,
+            sample([0, 5, 0, 0], [3, 1, 0, 2], [0, 5, 1, 0]),
+        ];
+        let options = consistent_candidates(&fixtures).unwrap();
+        assert_eq!(options[&7], vec![Opcode::Seti]);
+        assert_eq!(options[&3], vec![Opcode::Gtir]);
+        for mapping in all_mappings(&fixtures, 10).unwrap() {
+            assert_eq!(mapping.len(), 16);
+            assert_eq!((mapping[&7], mapping[&3]), (Opcode::Seti, Opcode::Gtir));
+        }
+
+        let mut both_seti = seti.clone();
+        both_seti.1[0] = 4;
+        assert_eq!(
+            infer_opcodes(&[seti.clone(), both_seti]),
+            Err(Error::InvalidProgram(
+                "the samples cannot be satisfied with a different opcode for every number"
+                    .to_string()
+            ))
+        );
+        let impossible = sample([0, 0, 0, 0], [2, 5, 0, 1], [9, 9, 9, 9]);
+        assert_eq!(
+            all_mappings(&[seti, impossible], 10),
+            Err(Error::InvalidProgram(
+                "no opcode matches every sample for 2".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_register_count() {
+        let cpu = CPU::<6>::new([0, 1, 2, 3, 4, 5]);
+        let instruction = Instruction {
+            opcode: Opcode::Addr,
+            a: 4,
+            b: 5,
+            c: 0,
+        };
+        assert_eq!(
+            cpu.execute(instruction).unwrap().registers(),
+            &[9, 1, 2, 3, 4, 5]
+        );
+        assert_eq!(CPU::<6>::from_slice(&[0; 6]), Ok(CPU::new([0; 6])));
+        assert!(CPU::<6>::from_slice(&[0; 4]).is_err());
+
+        let small = CPU::from_vec(&[0, 1, 2, 3]).unwrap();
+        assert!(small.execute(instruction).is_err());
+        let bad_target = Instruction {
+            opcode: Opcode::Seti,
+            a: 1,
+            b: 0,
+            c: 4,
+        };
+        assert!(small.execute(bad_target).is_err());
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let addr = Instruction {
+            opcode: Opcode::Addr,
+            a: 0,
+            b: 1,
+            c: 2,
+        };
+        let cpu = CPU::new([usize::MAX, 2, 0, 0]);
+        assert_eq!(
+            cpu.execute(addr),
+            Err(Error::InvalidProgram(format!(
+                "{} + 2 overflows",
+                usize::MAX
+            )))
+        );
+        let wrapping = cpu.with_arithmetic(Arithmetic::Wrapping);
+        assert_eq!(wrapping.execute(addr).unwrap().registers()[2], 1);
+        let saturating = cpu.with_arithmetic(Arithmetic::Saturating);
+        assert_eq!(saturating.execute(addr).unwrap().registers()[2], usize::MAX);
+
+        let signed: CPU<4, i64> = CPU::new([-3, 2, 0, 0]);
+        assert_eq!(signed.execute(addr).unwrap().registers(), &[-3, 2, -1, 0]);
+        let gtrr = Instruction {
+            opcode: Opcode::Gtrr,
+            ..addr
+        };
+        assert_eq!(signed.execute(gtrr).unwrap().registers()[2], 0);
+        let muli = Instruction {
+            opcode: Opcode::Muli,
+            b: 5,
+            ..addr
+        };
+        assert_eq!(signed.execute(muli).unwrap().registers()[2], -15);
+        let seti = Instruction {
+            opcode: Opcode::Seti,
+            a: usize::MAX,
+            ..addr
+        };
+        assert!(signed.execute(seti).is_err());
+    }
+
+    /// Each opcode as the puzzle describes it, written without `CPU`: the value stored in
+    /// register C, with A and B read as the letters of the name say. Computed in `u128` so
+    /// overflow shows instead of wrapping. `None` when a register does not exist.
+    fn reference(opcode: Opcode, r: &[usize], a: usize, b: usize) -> Option<u128> {
+        let name = opcode.name().as_bytes();
+        let read = |kind: u8, v: usize| match kind {
+            b'r' => r.get(v).map(|&x| x as u128),
+            _ => Some(v as u128),
+        };
+        // addr/addi and friends always read A from a register; setr/seti name A's kind;
+        // comparisons name both.
+        let last = name[3];
+        Some(match &name[..2] {
+            b"ad" => read(b'r', a)? + read(last, b)?,
+            b"mu" => read(b'r', a)? * read(last, b)?,
+            b"ba" => read(b'r', a)? & read(last, b)?,
+            b"bo" => read(b'r', a)? | read(last, b)?,
+            b"se" => read(last, a)?,
+            b"gt" => (read(name[2], a)? > read(last, b)?) as u128,
+            b"eq" => (read(name[2], a)? == read(last, b)?) as u128,
+            _ => unreachable!("unknown opcode {:?}", opcode),
+        })
+    }
+
+    /// The registers after `reference` runs, or `None` when the instruction fails.
+    fn reference_after(
+        opcode: Opcode,
+        before: &[usize],
+        operands: [usize; 3],
+        arithmetic: Arithmetic,
+    ) -> Option<Vec<usize>> {
+        let [a, b, c] = operands;
+        let value = reference(opcode, before, a, b)?;
+        let value = match arithmetic {
+            Arithmetic::Checked => usize::try_from(value).ok()?,
+            Arithmetic::Wrapping => value as usize,
+            Arithmetic::Saturating => usize::try_from(value).unwrap_or(usize::MAX),
+        };
+        let mut after = before.to_vec();
+        *after.get_mut(c)? = value;
+        Some(after)
+    }
+
+    /// Small numbers, so registers and immediates often coincide, mixed with huge ones.
+    fn random_value(rng: &mut utils::Rng) -> usize {
+        match rng.below(4) {
+            0 => rng.below(4),
+            1 => rng.below(16),
+            2 => usize::MAX - rng.below(3),
+            _ => rng.next_u64() as usize,
+        }
+    }
+
+    fn random_operand(rng: &mut utils::Rng) -> usize {
+        match rng.below(10) {
+            0 => random_value(rng),
+            1 => 4 + rng.below(3),
+            _ => rng.below(4),
+        }
+    }
+
+    type Method = fn(CPU, Instruction) -> Result<CPU>;
+
+    #[test]
+    fn test_opcodes_match_reference() {
+        let methods: [(Opcode, Method); 16] = [
+            (Opcode::Addr, CPU::addr),
+            (Opcode::Addi, CPU::addi),
+            (Opcode::Mulr, CPU::mulr),
+            (Opcode::Muli, CPU::muli),
+            (Opcode::Banr, CPU::banr),
+            (Opcode::Bani, CPU::bani),
+            (Opcode::Borr, CPU::borr),
+            (Opcode::Bori, CPU::bori),
+            (Opcode::Setr, CPU::setr),
+            (Opcode::Seti, CPU::seti),
+            (Opcode::Gtir, CPU::gtir),
+            (Opcode::Gtri, CPU::gtri),
+            (Opcode::Gtrr, CPU::gtrr),
+            (Opcode::Eqir, CPU::eqir),
+            (Opcode::Eqri, CPU::eqri),
+            (Opcode::Eqrr, CPU::eqrr),
+        ];
+        let modes = [
+            Arithmetic::Checked,
+            Arithmetic::Wrapping,
+            Arithmetic::Saturating,
+        ];
+        let mut rng = utils::Rng::new(16);
+        for _ in 0..3000 {
+            let before: Vec<usize> = (0..4).map(|_| random_value(&mut rng)).collect();
+            let operands = [0; 3].map(|_| random_operand(&mut rng));
+            let [a, b, c] = operands;
+            for &(opcode, method) in &methods {
+                let inst = Instruction { opcode, a, b, c };
+                for &mode in &modes {
+                    let cpu = CPU::from_vec(&before).unwrap().with_arithmetic(mode);
+                    let result = method(cpu, inst);
+                    assert_eq!(cpu.execute(inst), result, "{} on {:?}", inst, before);
+                    assert_eq!(
+                        result.ok().map(|after| after.registers().to_vec()),
+                        reference_after(opcode, &before, operands, mode),
+                        "{} on {:?} with {:?} arithmetic",
+                        inst,
+                        before,
+                        mode
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_which_opcodes_work_includes_truth() {
+        let mut rng = utils::Rng::new(2018);
+        for _ in 0..3000 {
+            let before: Vec<usize> = (0..4).map(|_| rng.below(4)).collect();
+            let opcode = Opcode::ALL[rng.below(16)];
+            let operand = |rng: &mut utils::Rng, kind| match kind {
+                Operand::Register => rng.below(4),
+                _ => rng.below(8),
+            };
+            let (kind_a, kind_b) = opcode.inputs();
+            let (a, b, c) = (
+                operand(&mut rng, kind_a),
+                operand(&mut rng, kind_b),
+                rng.below(4),
+            );
+            let cpu = CPU::from_vec(&before).unwrap();
+            let after = cpu.execute(Instruction { opcode, a, b, c }).unwrap();
+            let number = rng.below(16);
+            let works =
+                which_opcodes_work((cpu, vec![number, a, b, c], after), Opcode::ALL.to_vec());
+            assert!(
+                works.contains(&opcode),
+                "{:?} missing for {:?}",
+                opcode,
+                before
+            );
+            // Exactly the opcodes the reference says produce the same registers.
+            let expected: Vec<Opcode> = Opcode::ALL
+                .iter()
+                .cloned()
+                .filter(|&op| {
+                    reference_after(op, &before, [a, b, c], Arithmetic::Checked).as_deref()
+                        == Some(after.registers())
+                })
+                .collect();
+            assert_eq!(works, expected);
+        }
+    }
+
     #[test]
     fn test_addr() {
-        let before = CPU::from_vec(&vec![0, 1, 2, 3]);
+        let before = CPU::from_vec(&[0, 1, 2, 3]).unwrap();
         let instruction = Instruction {
             opcode: Opcode::Addr,
             a: 1,
             b: 3,
             c: 0,
         };
-        let after = CPU::from_vec(&vec![4, 1, 2, 3]);
-        assert_eq!(before.execute(instruction), after);
+        let after = CPU::from_vec(&[4, 1, 2, 3]).unwrap();
+        assert_eq!(before.execute(instruction), Ok(after));
     }
 
     #[test]
     fn test_addi() {
-        let before = CPU::from_vec(&vec![0, 1, 0, 0]);
+        let before = CPU::from_vec(&[0, 1, 0, 0]).unwrap();
         let instruction = Instruction {
             opcode: Opcode::Addi,
             a: 1,
             b: 3,
             c: 0,
         };
-        let after = CPU::from_vec(&vec![4, 1, 0, 0]);
-        assert_eq!(before.execute(instruction), after);
+        let after = CPU::from_vec(&[4, 1, 0, 0]).unwrap();
+        assert_eq!(before.execute(instruction), Ok(after));
     }
 
     #[test]
     fn test_mulr() {
-        let before = CPU::from_vec(&vec![2, 2, 0, 0]);
+        let before = CPU::from_vec(&[2, 2, 0, 0]).unwrap();
         let instruction = Instruction {
             opcode: Opcode::Mulr,
             a: 0,
             b: 1,
             c: 3,
         };
-        let after = CPU::from_vec(&vec![2, 2, 0, 4]);
-        assert_eq!(before.execute(instruction), after);
+        let after = CPU::from_vec(&[2, 2, 0, 4]).unwrap();
+        assert_eq!(before.execute(instruction), Ok(after));
     }
 
     #[test]
     fn test_muli() {
-        let before = CPU::from_vec(&vec![2, 2, 0, 0]);
+        let before = CPU::from_vec(&[2, 2, 0, 0]).unwrap();
         let instruction = Instruction {
             opcode: Opcode::Muli,
             a: 0,
             b: 1,
             c: 3,
         };
-        let after = CPU::from_vec(&vec![2, 2, 0, 2]);
-        assert_eq!(before.execute(instruction), after);
+        let after = CPU::from_vec(&[2, 2, 0, 2]).unwrap();
+        assert_eq!(before.execute(instruction), Ok(after));
     }
 
     #[test]
     fn test_banr() {
-        let before = CPU::from_vec(&vec![5, 2, 0, 0]);
+        let before = CPU::from_vec(&[5, 2, 0, 0]).unwrap();
         let instruction = Instruction {
             opcode: Opcode::Banr,
             a: 0,
             b: 1,
             c: 3,
         };
-        let after = CPU::from_vec(&vec![5, 2, 0, 0]);
-        assert_eq!(before.execute(instruction), after);
+        let after = CPU::from_vec(&[5, 2, 0, 0]).unwrap();
+        assert_eq!(before.execute(instruction), Ok(after));
     }
 
     #[test]
     fn test_bani() {
-        let before = CPU::from_vec(&vec![5, 2, 0, 7]);
+        let before = CPU::from_vec(&[5, 2, 0, 7]).unwrap();
         let instruction = Instruction {
             opcode: Opcode::Bani,
             a: 0,
             b: 1,
             c: 3,
         };
-        let after = CPU::from_vec(&vec![5, 2, 0, 1]);
-        assert_eq!(before.execute(instruction), after);
+        let after = CPU::from_vec(&[5, 2, 0, 1]).unwrap();
+        assert_eq!(before.execute(instruction), Ok(after));
     }
 
     #[test]
     fn test_borr() {
-        let before = CPU::from_vec(&vec![5, 2, 0, 0]);
+        let before = CPU::from_vec(&[5, 2, 0, 0]).unwrap();
         let instruction = Instruction {
             opcode: Opcode::Borr,
             a: 0,
             b: 1,
             c: 3,
         };
-        let after = CPU::from_vec(&vec![5, 2, 0, 7]);
-        assert_eq!(before.execute(instruction), after);
+        let after = CPU::from_vec(&[5, 2, 0, 7]).unwrap();
+        assert_eq!(before.execute(instruction), Ok(after));
     }
 
     #[test]
     fn test_bori() {
-        let before = CPU::from_vec(&vec![5, 2, 0, 7]);
+        let before = CPU::from_vec(&[5, 2, 0, 7]).unwrap();
         let instruction = Instruction {
             opcode: Opcode::Bori,
             a: 0,
             b: 1,
             c: 3,
         };
-        let after = CPU::from_vec(&vec![5, 2, 0, 5]);
-        assert_eq!(before.execute(instruction), after);
+        let after = CPU::from_vec(&[5, 2, 0, 5]).unwrap();
+        assert_eq!(before.execute(instruction), Ok(after));
     }
 
     #[test]
     fn test_setr() {
-        let before = CPU::from_vec(&vec![0, 1, 0, 0]);
+        let before = CPU::from_vec(&[0, 1, 0, 0]).unwrap();
         let instruction = Instruction {
             opcode: Opcode::Setr,
             a: 1,
             b: 0,
             c: 3,
         };
-        let after = CPU::from_vec(&vec![0, 1, 0, 1]);
-        assert_eq!(before.execute(instruction), after);
+        let after = CPU::from_vec(&[0, 1, 0, 1]).unwrap();
+        assert_eq!(before.execute(instruction), Ok(after));
     }
 
     #[test]
     fn test_seti() {
-        let before = CPU::from_vec(&vec![0, 1, 0, 0]);
+        let before = CPU::from_vec(&[0, 1, 0, 0]).unwrap();
         let instruction = Instruction {
             opcode: Opcode::Seti,
             a: 2,
             b: 0,
             c: 3,
         };
-        let after = CPU::from_vec(&vec![0, 1, 0, 2]);
-        assert_eq!(before.execute(instruction), after);
+        let after = CPU::from_vec(&[0, 1, 0, 2]).unwrap();
+        assert_eq!(before.execute(instruction), Ok(after));
     }
 
     #[test]
     fn test_gtir() {
-        let before = CPU::from_vec(&vec![0, 0, 0, 0]);
+        let before = CPU::from_vec(&[0, 0, 0, 0]).unwrap();
         let instruction = Instruction {
             opcode: Opcode::Gtir,
             a: 1,
             b: 0,
             c: 3,
         };
-        let after = CPU::from_vec(&vec![0, 0, 0, 1]);
-        assert_eq!(before.execute(instruction), after);
+        let after = CPU::from_vec(&[0, 0, 0, 1]).unwrap();
+        assert_eq!(before.execute(instruction), Ok(after));
     }
 
     #[test]
     fn test_gtri() {
-        let before = CPU::from_vec(&vec![0, 1, 0, 0]);
+        let before = CPU::from_vec(&[0, 1, 0, 0]).unwrap();
         let instruction = Instruction {
             opcode: Opcode::Gtri,
             a: 1,
             b: 0,
             c: 3,
         };
-        let after = CPU::from_vec(&vec![0, 1, 0, 1]);
-        assert_eq!(before.execute(instruction), after);
+        let after = CPU::from_vec(&[0, 1, 0, 1]).unwrap();
+        assert_eq!(before.execute(instruction), Ok(after));
     }
 
     #[test]
     fn test_gtrr() {
-        let before = CPU::from_vec(&vec![0, 0, 0, 0]);
+        let before = CPU::from_vec(&[0, 0, 0, 0]).unwrap();
         let instruction = Instruction {
             opcode: Opcode::Gtrr,
             a: 0,
             b: 1,
             c: 3,
         };
-        let after = CPU::from_vec(&vec![0, 0, 0, 0]);
-        assert_eq!(before.execute(instruction), after);
+        let after = CPU::from_vec(&[0, 0, 0, 0]).unwrap();
+        assert_eq!(before.execute(instruction), Ok(after));
     }
 
     #[test]
     fn test_eqir() {
-        let before = CPU::from_vec(&vec![0, 0, 0, 0]);
+        let before = CPU::from_vec(&[0, 0, 0, 0]).unwrap();
         let instruction = Instruction {
             opcode: Opcode::Eqir,
             a: 0,
             b: 0,
             c: 3,
         };
-        let after = CPU::from_vec(&vec![0, 0, 0, 1]);
-        assert_eq!(before.execute(instruction), after);
+        let after = CPU::from_vec(&[0, 0, 0, 1]).unwrap();
+        assert_eq!(before.execute(instruction), Ok(after));
     }
 
     #[test]
     fn test_eqri() {
-        let before = CPU::from_vec(&vec![0, 2, 0, 0]);
+        let before = CPU::from_vec(&[0, 2, 0, 0]).unwrap();
         let instruction = Instruction {
             opcode: Opcode::Eqri,
             a: 1,
             b: 2,
             c: 3,
         };
-        let after = CPU::from_vec(&vec![0, 2, 0, 1]);
-        assert_eq!(before.execute(instruction), after);
+        let after = CPU::from_vec(&[0, 2, 0, 1]).unwrap();
+        assert_eq!(before.execute(instruction), Ok(after));
     }
 
     #[test]
     fn test_eqrr() {
-        let before = CPU::from_vec(&vec![1, 0, 1, 0]);
+        let before = CPU::from_vec(&[1, 0, 1, 0]).unwrap();
         let instruction = Instruction {
             opcode: Opcode::Eqrr,
             a: 0,
             b: 2,
             c: 3,
         };
-        let after = CPU::from_vec(&vec![1, 0, 1, 1]);
-        assert_eq!(before.execute(instruction), after);
+        let after = CPU::from_vec(&[1, 0, 1, 1]).unwrap();
+        assert_eq!(before.execute(instruction), Ok(after));
     }
 }
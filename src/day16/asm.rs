@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+
+use super::program::Program;
+use super::{Instruction, Opcode};
+use crate::error::{Error, Result};
+use crate::utils;
+
+/// One source line that holds an instruction, kept until labels are known.
+struct Pending<'a> {
+    line: &'a str,
+    n: usize,
+    opcode: Opcode,
+    operands: Vec<&'a str>,
+}
+
+fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_') && Opcode::from_name(name).is_none()
+}
+
+/// Resolves an operand: a number, a label (the index of the instruction it marks), or a label
+/// plus or minus a number. Since the instruction pointer is incremented after every
+/// instruction, a jump that should continue at `loop` is written `seti loop-1 0 <ip>`.
+fn operand(token: &str, labels: &HashMap<&str, usize>) -> std::result::Result<usize, String> {
+    if let Ok(value) = token.parse() {
+        return Ok(value);
+    }
+    let split = token.find(['+', '-']).unwrap_or(token.len());
+    let (name, offset) = token.split_at(split);
+    let &target = labels.get(name).ok_or_else(|| {
+        if is_label(name) {
+            format!("undefined label {:?}", name)
+        } else {
+            format!("bad operand {:?}", token)
+        }
+    })?;
+    if offset.is_empty() {
+        return Ok(target);
+    }
+    let amount: usize = offset[1..]
+        .parse()
+        .map_err(|_| format!("bad operand {:?}", token))?;
+    let value = if offset.starts_with('+') {
+        target.checked_add(amount)
+    } else {
+        target.checked_sub(amount)
+    };
+    value.ok_or_else(|| format!("operand {:?} is out of range", token))
+}
+
+/// Assembles program text. Besides `opcode A B C` lines and an optional `#ip N` directive, the
+/// text may contain `;` comments and `name:` labels, either alone on a line or in front of an
+/// instruction. Labels can be used wherever a number is expected:
+///
+/// ```text
+/// #ip 3
+///         seti 0 0 1      ; r1 = 0
+/// loop:   addi 1 1 1
+///         gtri 1 9 2
+///         addr 2 3 3      ; skip the jump once r1 > 9
+///         seti loop-1 0 3
+/// ```
+pub fn assemble(text: &str) -> Result<Program> {
+    let mut ip = None;
+    let mut labels: HashMap<&str, usize> = HashMap::new();
+    let mut pending = vec![];
+
+    for (n, line) in text.lines().enumerate() {
+        let n = n + 1;
+        let code = line.split(';').next().unwrap_or("");
+        let mut tokens: Vec<&str> = code.split_whitespace().collect();
+        let err =
+            |token: &str, message: String| Error::parse(n, utils::column_of(line, token), message);
+
+        while let Some(label) = tokens.first().and_then(|t| t.strip_suffix(':')) {
+            if !is_label(label) {
+                return Err(err(tokens[0], format!("bad label {:?}", label)));
+            }
+            if labels.insert(label, pending.len()).is_some() {
+                return Err(err(tokens[0], format!("duplicate label {:?}", label)));
+            }
+            tokens.remove(0);
+        }
+        if tokens.is_empty() {
+            continue;
+        }
+
+        if tokens[0] == "#ip" {
+            if tokens.len() != 2 {
+                return Err(err(tokens[0], "expected `#ip N`".to_string()));
+            }
+            if ip.is_some() {
+                return Err(err(tokens[0], "duplicate #ip directive".to_string()));
+            }
+            let register = tokens[1]
+                .parse()
+                .map_err(|_| err(tokens[1], format!("bad register {:?}", tokens[1])))?;
+            ip = Some(register);
+            continue;
+        }
+
+        let opcode = Opcode::from_name(tokens[0])
+            .ok_or_else(|| err(tokens[0], format!("unknown opcode {:?}", tokens[0])))?;
+        if tokens.len() != 4 {
+            return Err(err(
+                tokens[0],
+                format!("{} takes 3 operands, found {}", tokens[0], tokens.len() - 1),
+            ));
+        }
+        pending.push(Pending {
+            line,
+            n,
+            opcode,
+            operands: tokens[1..].to_vec(),
+        });
+    }
+
+    let mut instructions = Vec::with_capacity(pending.len());
+    for p in pending {
+        let mut values = [0usize; 3];
+        for (value, token) in values.iter_mut().zip(&p.operands) {
+            *value = operand(token, &labels)
+                .map_err(|message| Error::parse(p.n, utils::column_of(p.line, token), message))?;
+        }
+        let [a, b, c] = values;
+        instructions.push(Instruction {
+            opcode: p.opcode,
+            a,
+            b,
+            c,
+        });
+    }
+    Ok(Program { ip, instructions })
+}
+
+/// Prints a program in the plain form `assemble` reads, one instruction per line.
+pub fn print(program: &Program) -> String {
+    let mut out = String::new();
+    if let Some(r) = program.ip {
+        out.push_str(&format!("#ip {}\n", r));
+    }
+    for inst in &program.instructions {
+        out.push_str(&format!("{}\n", inst));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::day16::program::Machine;
+
+    const COUNTER: &str = "#ip 3
+        seti 0 0 1      ; r1 = 0
+loop:   addi 1 1 1
+        gtri 1 9 2
+        addr 2 3 3      ; skip the jump once r1 > 9
+        seti loop-1 0 3
+done:
+";
+
+    #[test]
+    fn test_assemble_labels() {
+        let program = assemble(COUNTER).unwrap();
+        assert_eq!(program.ip, Some(3));
+        assert_eq!(program.instructions.len(), 5);
+        assert_eq!(
+            program.instructions[4],
+            Instruction {
+                opcode: Opcode::Seti,
+                a: 0,
+                b: 0,
+                c: 3
+            }
+        );
+
+        let mut machine: Machine = Machine::new(&program).unwrap();
+        machine.run(&program).unwrap();
+        assert_eq!(machine.registers()[1], 10);
+
+        let program = assemble("start: end: seti end+2 start 0").unwrap();
+        assert_eq!(program.instructions[0].a, 2);
+        assert_eq!(program.instructions[0].b, 0);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let program = assemble(COUNTER).unwrap();
+        let text = print(&program);
+        assert_eq!(
+            text,
+            "#ip 3\nseti 0 0 1\naddi 1 1 1\ngtri 1 9 2\naddr 2 3 3\nseti 0 0 3\n"
+        );
+        assert_eq!(assemble(&text), Ok(program));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            assemble("seti 1 2 3\n  mov 1 2 3").unwrap_err(),
+            Error::parse(2, 3, "unknown opcode \"mov\"")
+        );
+        assert_eq!(
+            assemble("seti nowhere 0 1").unwrap_err(),
+            Error::parse(1, 6, "undefined label \"nowhere\"")
+        );
+        assert_eq!(
+            assemble("a:\na: seti 0 0 0").unwrap_err(),
+            Error::parse(2, 1, "duplicate label \"a\"")
+        );
+        assert_eq!(
+            assemble("a: seti a-1 0 0").unwrap_err(),
+            Error::parse(1, 9, "operand \"a-1\" is out of range")
+        );
+        assert_eq!(
+            assemble("seti 1 2 3 4").unwrap_err(),
+            Error::parse(1, 1, "seti takes 3 operands, found 4")
+        );
+        assert_eq!(
+            assemble("seti 1 ?x 3").unwrap_err(),
+            Error::parse(1, 8, "bad operand \"?x\"")
+        );
+        assert!(assemble("addi: seti 0 0 0").is_err());
+    }
+}
@@ -0,0 +1,477 @@
+use super::program::Program;
+use super::{Arithmetic, Instruction, Opcode, Operand, Word};
+
+/// An instruction input once the program is known: a register, or a value fixed at compile
+/// time. Reads of the instruction pointer register are values, as the register always holds
+/// the index of the instruction reading it.
+#[derive(Copy, Clone)]
+enum Source<W> {
+    Register(usize),
+    Value(W),
+}
+
+/// What an instruction computes, specialised by input kind so running it needs no further
+/// decoding. Instructions whose inputs are all values are folded to `SetV`; ones that cannot
+/// succeed are `Fail`, and are left to the interpreter to report.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Compute<W> {
+    AddRR(usize, usize),
+    AddRV(usize, W),
+    MulRR(usize, usize),
+    MulRV(usize, W),
+    AndRR(usize, usize),
+    AndRV(usize, W),
+    OrRR(usize, usize),
+    OrRV(usize, W),
+    SetR(usize),
+    SetV(W),
+    GtRR(usize, usize),
+    GtRV(usize, W),
+    LtRV(usize, W),
+    EqRR(usize, usize),
+    EqRV(usize, W),
+    Fail,
+}
+
+/// Where execution continues when it is known before running: the instruction, how many
+/// steps it took to get there, and what the instruction pointer register then holds.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Target<W> {
+    to: usize,
+    steps: u64,
+    ip: W,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Next<W> {
+    Fall(Target<W>),
+    /// The instruction writes a computed value to the instruction pointer register.
+    Jump,
+    /// The instruction is a comparison followed by `addr flag ip ip`; the targets are for
+    /// flags 0 and 1.
+    Branch(Target<W>, Target<W>),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Op<W> {
+    compute: Compute<W>,
+    c: usize,
+    next: Next<W>,
+}
+
+/// A program pre-decoded into threaded code: one `Op` per instruction with its inputs
+/// resolved, its result register, and its successor. Jumps to fixed places are followed at
+/// compile time and fused into the instruction before them, so a loop's unconditional jump
+/// back, or the `addr flag ip ip` after a comparison, costs nothing when running.
+pub struct Compiled<const N: usize, W: Word = usize> {
+    pub(crate) program: Program,
+    pub(crate) arithmetic: Arithmetic,
+    code: Vec<Op<W>>,
+    /// The most steps a single `Op` stands for.
+    stride: u64,
+}
+
+/// Where `Compiled::execute` stopped.
+pub(crate) struct Stop<W> {
+    pub pc: usize,
+    pub steps: u64,
+    /// The value of the instruction pointer register, if any instruction ran.
+    pub ip: Option<W>,
+}
+
+fn source<const N: usize, W: Word>(
+    kind: Operand,
+    v: usize,
+    index: usize,
+    ip: Option<usize>,
+) -> Option<Source<W>> {
+    match kind {
+        Operand::Register if Some(v) == ip => W::from_immediate(index).map(Source::Value),
+        Operand::Register if v < N => Some(Source::Register(v)),
+        Operand::Register => None,
+        Operand::Immediate => W::from_immediate(v).map(Source::Value),
+        Operand::Unused => Some(Source::Value(W::default())),
+    }
+}
+
+fn decode<const N: usize, W: Word>(
+    inst: Instruction,
+    index: usize,
+    ip: Option<usize>,
+    arithmetic: Arithmetic,
+) -> Compute<W> {
+    use Compute::*;
+    use Source::{Register as R, Value as V};
+    let (kind_a, kind_b) = inst.opcode.inputs();
+    let (a, b) = match (
+        source::<N, W>(kind_a, inst.a, index, ip),
+        source::<N, W>(kind_b, inst.b, index, ip),
+    ) {
+        (Some(a), Some(b)) if inst.c < N => (a, b),
+        _ => return Fail,
+    };
+    let constant = |value: Option<W>| value.map_or(Fail, SetV);
+    match (inst.opcode, a, b) {
+        (Opcode::Addr | Opcode::Addi, R(a), R(b)) => AddRR(a, b),
+        (Opcode::Addr | Opcode::Addi, R(a), V(b)) | (Opcode::Addr | Opcode::Addi, V(b), R(a)) => {
+            AddRV(a, b)
+        }
+        (Opcode::Addr | Opcode::Addi, V(a), V(b)) => constant(a.add(b, arithmetic)),
+        (Opcode::Mulr | Opcode::Muli, R(a), R(b)) => MulRR(a, b),
+        (Opcode::Mulr | Opcode::Muli, R(a), V(b)) | (Opcode::Mulr | Opcode::Muli, V(b), R(a)) => {
+            MulRV(a, b)
+        }
+        (Opcode::Mulr | Opcode::Muli, V(a), V(b)) => constant(a.mul(b, arithmetic)),
+        (Opcode::Banr | Opcode::Bani, R(a), R(b)) => AndRR(a, b),
+        (Opcode::Banr | Opcode::Bani, R(a), V(b)) | (Opcode::Banr | Opcode::Bani, V(b), R(a)) => {
+            AndRV(a, b)
+        }
+        (Opcode::Banr | Opcode::Bani, V(a), V(b)) => SetV(a.bitand(b)),
+        (Opcode::Borr | Opcode::Bori, R(a), R(b)) => OrRR(a, b),
+        (Opcode::Borr | Opcode::Bori, R(a), V(b)) | (Opcode::Borr | Opcode::Bori, V(b), R(a)) => {
+            OrRV(a, b)
+        }
+        (Opcode::Borr | Opcode::Bori, V(a), V(b)) => SetV(a.bitor(b)),
+        (Opcode::Setr | Opcode::Seti, R(a), _) => SetR(a),
+        (Opcode::Setr | Opcode::Seti, V(a), _) => SetV(a),
+        (Opcode::Gtir | Opcode::Gtri | Opcode::Gtrr, R(a), R(b)) => GtRR(a, b),
+        (Opcode::Gtir | Opcode::Gtri | Opcode::Gtrr, R(a), V(b)) => GtRV(a, b),
+        (Opcode::Gtir | Opcode::Gtri | Opcode::Gtrr, V(a), R(b)) => LtRV(b, a),
+        (Opcode::Gtir | Opcode::Gtri | Opcode::Gtrr, V(a), V(b)) => SetV(W::from_bool(a > b)),
+        (Opcode::Eqir | Opcode::Eqri | Opcode::Eqrr, R(a), R(b)) => EqRR(a, b),
+        (Opcode::Eqir | Opcode::Eqri | Opcode::Eqrr, R(a), V(b))
+        | (Opcode::Eqir | Opcode::Eqri | Opcode::Eqrr, V(b), R(a)) => EqRV(a, b),
+        (Opcode::Eqir | Opcode::Eqri | Opcode::Eqrr, V(a), V(b)) => SetV(W::from_bool(a == b)),
+    }
+}
+
+fn is_comparison<W>(compute: Compute<W>) -> bool {
+    use Compute::*;
+    matches!(
+        compute,
+        GtRR(..) | GtRV(..) | LtRV(..) | EqRR(..) | EqRV(..)
+    )
+}
+
+/// Whether `inst` is `addr flag ip ip`, skipping the next instruction when the flag is set.
+fn is_skip(inst: Option<&Instruction>, flag: usize, ip: Option<usize>) -> bool {
+    match (inst, ip) {
+        (Some(inst), Some(ip)) => {
+            inst.opcode == Opcode::Addr
+                && inst.c == ip
+                && flag != ip
+                && ((inst.a == flag && inst.b == ip) || (inst.a == ip && inst.b == flag))
+        }
+        _ => false,
+    }
+}
+
+impl<const N: usize, W: Word> Compiled<N, W> {
+    /// Compiles `program` for a machine with `N` registers using `arithmetic`.
+    pub fn new(program: &Program, arithmetic: Arithmetic) -> Self {
+        let ip = program.ip;
+        let computes: Vec<Compute<W>> = program
+            .instructions
+            .iter()
+            .enumerate()
+            .map(|(i, &inst)| decode::<N, W>(inst, i, ip, arithmetic))
+            .collect();
+        // The value `index` writes to the instruction pointer register, if it is a jump to a
+        // fixed place.
+        let jump = |index: usize| match computes.get(index) {
+            Some(&Compute::SetV(value)) if Some(program.instructions[index].c) == ip => Some(value),
+            _ => None,
+        };
+        // Follows jumps to fixed places from `target`. A chain of them that loops is left
+        // for the running code, so it still counts its steps.
+        let chase = |mut target: Target<W>| {
+            for _ in 0..computes.len() {
+                match jump(target.to) {
+                    Some(value) => {
+                        target = Target {
+                            to: value.next_index(),
+                            steps: target.steps + 1,
+                            ip: value,
+                        }
+                    }
+                    None => break,
+                }
+            }
+            target
+        };
+        // Without a bound register the `ip` of a target is never used.
+        let fall = |index: usize, steps: u64| {
+            chase(Target {
+                to: index + 1,
+                steps,
+                ip: W::from_immediate(index).unwrap_or_default(),
+            })
+        };
+        let code: Vec<Op<W>> = program
+            .instructions
+            .iter()
+            .enumerate()
+            .map(|(i, &inst)| {
+                let compute = computes[i];
+                let next = if let Some(value) = jump(i) {
+                    Next::Fall(chase(Target {
+                        to: value.next_index(),
+                        steps: 1,
+                        ip: value,
+                    }))
+                } else if Some(inst.c) == ip {
+                    Next::Jump
+                } else if is_comparison(compute)
+                    && is_skip(program.instructions.get(i + 1), inst.c, ip)
+                {
+                    Next::Branch(fall(i + 1, 2), fall(i + 2, 2))
+                } else {
+                    Next::Fall(fall(i, 1))
+                };
+                Op {
+                    compute,
+                    c: inst.c,
+                    next,
+                }
+            })
+            .collect();
+        let stride = code
+            .iter()
+            .map(|op| match op.next {
+                Next::Fall(target) => target.steps,
+                Next::Jump => 1,
+                Next::Branch(zero, one) => zero.steps.max(one.steps),
+            })
+            .max()
+            .unwrap_or(1);
+        Compiled {
+            program: program.clone(),
+            arithmetic,
+            code,
+            stride,
+        }
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// Runs from `pc` until the program halts, an instruction fails, or running another
+    /// `Op` could take more than `budget` steps. The bound register is not kept up to date
+    /// while running; `Stop::ip` is its final value.
+    pub(crate) fn execute(&self, r: &mut [W; N], pc: usize, budget: u64) -> Stop<W> {
+        // One loop per arithmetic mode, so the mode is not looked at on every instruction.
+        match self.arithmetic {
+            Arithmetic::Checked => self.run_loop(
+                r,
+                pc,
+                budget,
+                |x, y| x.add(y, Arithmetic::Checked),
+                |x, y| x.mul(y, Arithmetic::Checked),
+            ),
+            Arithmetic::Wrapping => self.run_loop(
+                r,
+                pc,
+                budget,
+                |x, y| x.add(y, Arithmetic::Wrapping),
+                |x, y| x.mul(y, Arithmetic::Wrapping),
+            ),
+            Arithmetic::Saturating => self.run_loop(
+                r,
+                pc,
+                budget,
+                |x, y| x.add(y, Arithmetic::Saturating),
+                |x, y| x.mul(y, Arithmetic::Saturating),
+            ),
+        }
+    }
+
+    #[inline(always)]
+    fn run_loop<A, M>(&self, r: &mut [W; N], mut pc: usize, budget: u64, add: A, mul: M) -> Stop<W>
+    where
+        A: Fn(W, W) -> Option<W>,
+        M: Fn(W, W) -> Option<W>,
+    {
+        use Compute::*;
+        let (mut steps, mut ip) = (0, W::default());
+        while budget - steps >= self.stride {
+            let op = match self.code.get(pc) {
+                Some(op) => op,
+                None => break,
+            };
+            let value = match op.compute {
+                AddRR(a, b) => add(r[a], r[b]),
+                AddRV(a, b) => add(r[a], b),
+                MulRR(a, b) => mul(r[a], r[b]),
+                MulRV(a, b) => mul(r[a], b),
+                AndRR(a, b) => Some(r[a].bitand(r[b])),
+                AndRV(a, b) => Some(r[a].bitand(b)),
+                OrRR(a, b) => Some(r[a].bitor(r[b])),
+                OrRV(a, b) => Some(r[a].bitor(b)),
+                SetR(a) => Some(r[a]),
+                SetV(a) => Some(a),
+                GtRR(a, b) => Some(W::from_bool(r[a] > r[b])),
+                GtRV(a, b) => Some(W::from_bool(r[a] > b)),
+                LtRV(a, b) => Some(W::from_bool(r[a] < b)),
+                EqRR(a, b) => Some(W::from_bool(r[a] == r[b])),
+                EqRV(a, b) => Some(W::from_bool(r[a] == b)),
+                Fail => None,
+            };
+            let value = match value {
+                Some(value) => value,
+                None => break,
+            };
+            r[op.c] = value;
+            let target = match op.next {
+                Next::Fall(target) => target,
+                Next::Jump => Target {
+                    to: value.next_index(),
+                    steps: 1,
+                    ip: value,
+                },
+                // Written as a guard so it compiles to a predicted branch. Selecting the
+                // target with a conditional move would make the next instruction wait for
+                // the comparison, which more than doubles the time per instruction.
+                Next::Branch(zero, _) if value == W::default() => zero,
+                Next::Branch(_, one) => one,
+            };
+            pc = target.to;
+            steps += target.steps;
+            ip = target.ip;
+        }
+        Stop {
+            pc,
+            steps,
+            ip: if steps > 0 { Some(ip) } else { None },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::day16::program::Machine;
+    use crate::error::Result;
+    use crate::utils::Rng;
+
+    /// A random program, usually binding a register to the instruction pointer. Registers
+    /// are occasionally out of range and immediates occasionally huge, to exercise the error
+    /// paths; comparisons are often followed by `addr flag ip ip`, to exercise branches.
+    fn random_program(rng: &mut Rng) -> Program {
+        let len = 2 + rng.below(14);
+        let ip = if rng.below(4) == 0 {
+            None
+        } else {
+            Some(rng.below(6))
+        };
+        let mut instructions: Vec<Instruction> = vec![];
+        while instructions.len() < len {
+            let operand = |rng: &mut Rng| match rng.below(20) {
+                0 => 6,
+                1 => usize::MAX - rng.below(3),
+                2..=9 => rng.below(len + 2),
+                _ => rng.below(6),
+            };
+            let inst = Instruction {
+                opcode: Opcode::ALL[rng.below(16)],
+                a: operand(rng),
+                b: operand(rng),
+                c: if rng.below(30) == 0 { 6 } else { rng.below(6) },
+            };
+            instructions.push(inst);
+            if let Some(ip) = ip {
+                if inst.opcode.name().starts_with(['g', 'e']) && rng.below(2) == 0 {
+                    instructions.push(Instruction {
+                        opcode: Opcode::Addr,
+                        a: inst.c,
+                        b: ip,
+                        c: ip,
+                    });
+                }
+            }
+        }
+        Program { ip, instructions }
+    }
+
+    fn interpret<W: Word>(
+        machine: &mut Machine<6, W>,
+        program: &Program,
+        limit: u64,
+    ) -> Result<()> {
+        while machine.steps() < limit && machine.step(program)? {}
+        Ok(())
+    }
+
+    fn differential<W: Word + PartialEq>(seed: u64, arithmetic: Arithmetic, initial: [W; 6]) {
+        let mut rng = Rng::new(seed);
+        for case in 0..1000 {
+            let program = random_program(&mut rng);
+            let compiled = Compiled::new(&program, arithmetic);
+            let fresh = || {
+                let mut machine: Machine<6, W> = Machine::new(&program).unwrap();
+                machine.cpu = machine.cpu.with_arithmetic(arithmetic);
+                machine.cpu.registers_mut().copy_from_slice(&initial);
+                machine
+            };
+            let (mut expected, mut actual) = (fresh(), fresh());
+            let limit = 1 + rng.below(300) as u64;
+            let expected_result = interpret(&mut expected, &program, limit);
+            let actual_result = actual.run_compiled_limit(&compiled, limit).map(|_| ());
+            assert_eq!(actual_result, expected_result, "case {}: {}", case, program);
+            assert_eq!(actual, expected, "case {}: {}", case, program);
+        }
+    }
+
+    #[test]
+    fn test_differential_unsigned() {
+        differential(0x5eed, Arithmetic::Checked, [0usize; 6]);
+        differential(7, Arithmetic::Wrapping, [1, 2, 3, 4, 5, usize::MAX]);
+        differential(99, Arithmetic::Saturating, [usize::MAX / 2; 6]);
+    }
+
+    #[test]
+    fn test_differential_signed() {
+        differential(12345, Arithmetic::Checked, [-3i64, 0, 7, i64::MIN, 1, -1]);
+        differential(54321, Arithmetic::Wrapping, [i64::MAX, -2, 0, 0, 3, 0]);
+    }
+
+    #[test]
+    fn test_fused_jumps() {
+        // Counts r1 up to 100; the comparison and the two jumps after it become one branch.
+        let program =
+            Program::parse("#ip 3\nseti 0 0 1\naddi 1 1 1\ngtri 1 99 2\naddr 2 3 3\nseti 0 0 3")
+                .unwrap();
+        let compiled: Compiled<6> = Compiled::new(&program, Arithmetic::Checked);
+        assert_eq!(
+            compiled.code[2].next,
+            Next::Branch(
+                Target {
+                    to: 1,
+                    steps: 3,
+                    ip: 0
+                },
+                Target {
+                    to: 5,
+                    steps: 2,
+                    ip: 4
+                }
+            )
+        );
+
+        let mut expected: Machine = Machine::new(&program).unwrap();
+        expected.run(&program).unwrap();
+        let mut actual: Machine = Machine::new(&program).unwrap();
+        assert_eq!(actual.run_compiled(&compiled), Ok(expected.steps()));
+        assert_eq!(actual, expected);
+        assert_eq!(actual.registers()[1], 100);
+    }
+
+    #[test]
+    fn test_mismatched_machine() {
+        let program = Program::parse("#ip 0\nseti 0 0 1").unwrap();
+        let compiled = Compiled::new(&program, Arithmetic::Wrapping);
+        let mut machine: Machine = Machine::new(&program).unwrap();
+        assert!(machine.run_compiled(&compiled).is_err());
+        assert_eq!(machine.steps(), 0);
+    }
+}
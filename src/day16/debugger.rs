@@ -0,0 +1,419 @@
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use super::disasm;
+use super::program::{Machine, Program};
+
+/// How a register breakpoint compares the register against its value.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    const ALL: [(&'static str, Comparison); 6] = [
+        ("==", Comparison::Eq),
+        ("!=", Comparison::Ne),
+        ("<", Comparison::Lt),
+        ("<=", Comparison::Le),
+        (">", Comparison::Gt),
+        (">=", Comparison::Ge),
+    ];
+
+    fn from_symbol(symbol: &str) -> Option<Comparison> {
+        Comparison::ALL
+            .iter()
+            .find(|(s, _)| *s == symbol)
+            .map(|&(_, c)| c)
+    }
+
+    fn symbol(self) -> &'static str {
+        Comparison::ALL.iter().find(|(_, c)| *c == self).unwrap().0
+    }
+
+    fn holds(self, left: usize, right: usize) -> bool {
+        match self {
+            Comparison::Eq => left == right,
+            Comparison::Ne => left != right,
+            Comparison::Lt => left < right,
+            Comparison::Le => left <= right,
+            Comparison::Gt => left > right,
+            Comparison::Ge => left >= right,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// Stops before the instruction at this index runs.
+    Instruction(usize),
+    /// Stops as soon as the register comparison holds.
+    Register {
+        register: usize,
+        comparison: Comparison,
+        value: usize,
+    },
+}
+
+impl Breakpoint {
+    /// Parses `N` for an instruction index or `rN OP V` for a register condition.
+    pub fn parse(text: &str) -> Option<Breakpoint> {
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        match tokens[..] {
+            [index] => index.parse().ok().map(Breakpoint::Instruction),
+            [register, symbol, value] => Some(Breakpoint::Register {
+                register: parse_register(register)?,
+                comparison: Comparison::from_symbol(symbol)?,
+                value: value.parse().ok()?,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Whether the breakpoint fires after a step from `before` to `after`. Register conditions
+    /// fire when they become true, so continuing does not stop again while they stay true.
+    fn triggered<const N: usize>(self, before: &Machine<N>, after: &Machine<N>) -> bool {
+        match self {
+            Breakpoint::Instruction(_) => self.holds(after),
+            Breakpoint::Register { .. } => self.holds(after) && !self.holds(before),
+        }
+    }
+
+    fn holds<const N: usize>(self, machine: &Machine<N>) -> bool {
+        match self {
+            Breakpoint::Instruction(index) => machine.ip == index,
+            Breakpoint::Register {
+                register,
+                comparison,
+                value,
+            } => machine
+                .registers()
+                .get(register)
+                .is_some_and(|&r| comparison.holds(r, value)),
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Breakpoint::Instruction(index) => write!(f, "instruction {}", index),
+            Breakpoint::Register {
+                register,
+                comparison,
+                value,
+            } => write!(f, "r{} {} {}", register, comparison.symbol(), value),
+        }
+    }
+}
+
+fn parse_register(text: &str) -> Option<usize> {
+    text.strip_prefix('r')?.parse().ok()
+}
+
+/// How many instructions `continue` runs at most when not told, so a program that never halts
+/// hands control back.
+const CONTINUE_STEPS: u64 = 10_000_000;
+
+const HELP: &str = "\
+step [N]          run N instructions (default 1)
+continue [N]      run until a breakpoint is hit, the program halts, or N instructions have
+                  run (default 10000000)
+break N           stop before instruction N
+break rX OP V     stop when register X starts comparing true to V (OP: == != < <= > >=)
+delete N          remove breakpoint N
+breakpoints       list breakpoints
+regs              print the registers
+set rX V          store V in register X
+show              show the next instruction
+quit              leave the debugger
+";
+
+/// What the REPL should do after a command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Print(String),
+    Quit,
+}
+
+/// Steps through a `Program`, stopping at breakpoints and letting registers be inspected and
+/// changed in between.
+pub struct Debugger<'a, const N: usize> {
+    program: &'a Program,
+    machine: Machine<N>,
+    breakpoints: Vec<Breakpoint>,
+}
+
+impl<'a, const N: usize> Debugger<'a, N> {
+    pub fn new(program: &'a Program, machine: Machine<N>) -> Self {
+        Debugger {
+            program,
+            machine,
+            breakpoints: vec![],
+        }
+    }
+
+    pub fn machine(&self) -> &Machine<N> {
+        &self.machine
+    }
+
+    /// Runs a single command line and returns its output.
+    pub fn command(&mut self, line: &str) -> Outcome {
+        let mut words = line.split_whitespace();
+        let name = match words.next() {
+            Some(name) => name,
+            None => return Outcome::Print(String::new()),
+        };
+        let rest: Vec<&str> = words.collect();
+        let output = match (name, &rest[..]) {
+            ("step" | "s", []) => self.step(1),
+            ("step" | "s", [n]) => match n.parse() {
+                Ok(n) => self.step(n),
+                Err(_) => format!("bad step count {:?}\n", n),
+            },
+            ("continue" | "c", []) => self.resume(CONTINUE_STEPS),
+            ("continue" | "c", [n]) => match n.parse() {
+                Ok(n) => self.resume(n),
+                Err(_) => format!("bad step count {:?}\n", n),
+            },
+            ("break" | "b", args) if !args.is_empty() => match Breakpoint::parse(&args.join(" ")) {
+                Some(breakpoint) => {
+                    self.breakpoints.push(breakpoint);
+                    format!(
+                        "breakpoint {}: {}\n",
+                        self.breakpoints.len() - 1,
+                        breakpoint
+                    )
+                }
+                None => "expected `break N` or `break rX OP V`\n".to_string(),
+            },
+            ("delete" | "d", [n]) => match n.parse::<usize>() {
+                Ok(n) if n < self.breakpoints.len() => {
+                    format!("deleted {}\n", self.breakpoints.remove(n))
+                }
+                _ => format!("no breakpoint {}\n", n),
+            },
+            ("breakpoints", []) => self
+                .breakpoints
+                .iter()
+                .enumerate()
+                .map(|(i, b)| format!("{}: {}\n", i, b))
+                .collect(),
+            ("regs" | "r", []) => self.registers(),
+            ("set", [register, value]) => self.set(register, value),
+            ("show", []) => self.current(),
+            ("help" | "h", []) => HELP.to_string(),
+            ("quit" | "q", []) => return Outcome::Quit,
+            _ => format!("unknown command {:?}, try `help`\n", line.trim()),
+        };
+        Outcome::Print(output)
+    }
+
+    /// Reads commands until `quit` or the end of the input, writing each command's output.
+    pub fn repl(&mut self, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        write!(output, "{}", self.current())?;
+        write!(output, "(debug) ")?;
+        output.flush()?;
+        for line in input.lines() {
+            match self.command(&line?) {
+                Outcome::Print(text) => write!(output, "{}", text)?,
+                Outcome::Quit => return Ok(()),
+            }
+            write!(output, "(debug) ")?;
+            output.flush()?;
+        }
+        writeln!(output)
+    }
+
+    /// Executes one instruction, returning an explanation if execution cannot go on.
+    fn advance(&mut self) -> Option<String> {
+        match self.machine.step(self.program) {
+            Ok(true) => None,
+            Ok(false) => Some(format!("halted after {} steps\n", self.machine.steps())),
+            Err(e) => Some(format!("error: {}\n", e)),
+        }
+    }
+
+    fn step(&mut self, n: u64) -> String {
+        for _ in 0..n {
+            if let Some(stop) = self.advance() {
+                return stop;
+            }
+        }
+        self.current()
+    }
+
+    /// Runs at least one instruction, so continuing from a breakpoint makes progress, and at
+    /// most `budget`.
+    fn resume(&mut self, budget: u64) -> String {
+        for _ in 0..budget.max(1) {
+            let before = self.machine.clone();
+            if let Some(stop) = self.advance() {
+                return stop;
+            }
+            let hit = self
+                .breakpoints
+                .iter()
+                .position(|b| b.triggered(&before, &self.machine));
+            if let Some(i) = hit {
+                return format!(
+                    "breakpoint {}: {}\n{}",
+                    i,
+                    self.breakpoints[i],
+                    self.current()
+                );
+            }
+        }
+        format!("stopped after {} steps\n{}", budget.max(1), self.current())
+    }
+
+    fn registers(&self) -> String {
+        let registers: Vec<String> = self
+            .machine
+            .registers()
+            .iter()
+            .enumerate()
+            .map(|(i, r)| format!("r{}={}", i, r))
+            .collect();
+        format!("ip={} {}\n", self.machine.ip, registers.join(" "))
+    }
+
+    fn set(&mut self, register: &str, value: &str) -> String {
+        let value = match value.parse() {
+            Ok(value) => value,
+            Err(_) => return format!("bad value {:?}\n", value),
+        };
+        let slot =
+            parse_register(register).and_then(|r| self.machine.cpu.registers_mut().get_mut(r));
+        match slot {
+            Some(slot) => {
+                *slot = value;
+                self.registers()
+            }
+            None => format!("no register {:?}\n", register),
+        }
+    }
+
+    /// The next instruction, disassembled, after the step count and registers.
+    fn current(&self) -> String {
+        let next = match self.program.instructions.get(self.machine.ip) {
+            Some(&inst) => format!(
+                "{:>4}: {}  ; {}",
+                self.machine.ip,
+                disasm::format_instruction(inst, self.program.ip),
+                disasm::pseudo_code(inst, self.program.ip)
+            ),
+            None => "halted".to_string(),
+        };
+        format!("[{}] {}{}\n", self.machine.steps(), self.registers(), next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COUNTER: &str = "#ip 3
+seti 0 0 1
+addi 1 1 1
+gtri 1 9 2
+addr 2 3 3
+seti 0 0 3
+";
+
+    fn debugger(program: &Program) -> Debugger<'_, 6> {
+        Debugger::new(program, Machine::new(program).unwrap())
+    }
+
+    #[test]
+    fn test_parse_breakpoints() {
+        assert_eq!(Breakpoint::parse("12"), Some(Breakpoint::Instruction(12)));
+        assert_eq!(
+            Breakpoint::parse("r0 >= 5"),
+            Some(Breakpoint::Register {
+                register: 0,
+                comparison: Comparison::Ge,
+                value: 5
+            })
+        );
+        assert_eq!(Breakpoint::parse("r0 = 5"), None);
+        assert_eq!(Breakpoint::parse("x0 == 5"), None);
+        assert_eq!(Breakpoint::parse("r0 == 5").unwrap().to_string(), "r0 == 5");
+    }
+
+    #[test]
+    fn test_step_and_break() {
+        let program = Program::parse(COUNTER).unwrap();
+        let mut debugger = debugger(&program);
+        debugger.command("step 2");
+        assert_eq!(debugger.machine().registers()[1], 1);
+        assert_eq!(debugger.machine().ip, 2);
+
+        debugger.command("break r1 == 4");
+        debugger.command("continue");
+        assert_eq!(debugger.machine().registers()[1], 4);
+
+        debugger.command("delete 0");
+        debugger.command("break 4");
+        debugger.command("c");
+        assert_eq!(debugger.machine().ip, 4);
+        assert_eq!(debugger.machine().registers()[1], 4);
+
+        debugger.command("set r1 9");
+        debugger.command("delete 0");
+        assert_eq!(
+            debugger.command("continue"),
+            Outcome::Print("halted after 20 steps\n".to_string())
+        );
+        assert_eq!(debugger.machine().registers()[1], 10);
+    }
+
+    #[test]
+    fn test_continue_budget() {
+        // Counts r0 up forever.
+        let program = Program::parse("#ip 2\nseti 0 0 1\naddi 0 1 0\nseti 0 0 2").unwrap();
+        let mut debugger = debugger(&program);
+        let stop = match debugger.command("continue 5") {
+            Outcome::Print(text) => text,
+            Outcome::Quit => unreachable!(),
+        };
+        assert!(stop.starts_with("stopped after 5 steps\n[5] "));
+        assert_eq!(debugger.machine().registers()[0], 2);
+        assert_eq!(
+            debugger.command("continue x"),
+            Outcome::Print("bad step count \"x\"\n".to_string())
+        );
+    }
+
+    #[test]
+    fn test_output() {
+        let program = Program::parse(COUNTER).unwrap();
+        let mut debugger = debugger(&program);
+        assert_eq!(
+            debugger.command("show"),
+            Outcome::Print(
+                "[0] ip=0 r0=0 r1=0 r2=0 r3=0 r4=0 r5=0\n   0: seti 0 -> r1  ; r1 = 0\n"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            debugger.command("set r7 1"),
+            Outcome::Print("no register \"r7\"\n".to_string())
+        );
+        assert_eq!(debugger.command("quit"), Outcome::Quit);
+
+        let mut out = vec![];
+        debugger
+            .repl(io::Cursor::new("step\nregs\nbogus\nq\nstep\n"), &mut out)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("[1] ip=1 r0=0 r1=0"));
+        assert!(out.contains("unknown command \"bogus\""));
+        assert_eq!(debugger.machine().ip, 1);
+    }
+}
@@ -0,0 +1,630 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use super::program::Program;
+use super::{Instruction, Opcode, Operand};
+
+/// A comparison, as computed by the `gt*` and `eq*` opcodes or its negation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    left: String,
+    op: &'static str,
+    right: String,
+}
+
+impl Condition {
+    fn negate(&self) -> Condition {
+        let op = match self.op {
+            ">" => "<=",
+            "<=" => ">",
+            "==" => "!=",
+            _ => "==",
+        };
+        Condition {
+            left: self.left.clone(),
+            op,
+            right: self.right.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.left, self.op, self.right)
+    }
+}
+
+/// One instruction after jumps have been resolved, before structuring.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Assign(String),
+    /// Continues at the target when the condition holds, otherwise at the next instruction.
+    Branch(Condition, usize),
+    Goto(usize),
+    /// A jump whose target depends on a register, with the expression the pointer is set to.
+    Computed(String),
+    /// An instruction folded into its neighbour.
+    Nop,
+}
+
+/// Structured pseudo-code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stmt {
+    /// Marks where instruction `n` starts; only printed when a `goto` targets it.
+    Label(usize),
+    Assign(String),
+    If {
+        condition: Condition,
+        then: Vec<Stmt>,
+        otherwise: Vec<Stmt>,
+    },
+    Loop(Vec<Stmt>),
+    While(Condition, Vec<Stmt>),
+    DoWhile(Vec<Stmt>, Condition),
+    Break,
+    Continue,
+    Goto(usize),
+    Computed(String),
+    Halt,
+}
+
+fn is_comparison(opcode: Opcode) -> bool {
+    matches!(
+        opcode,
+        Opcode::Gtir | Opcode::Gtri | Opcode::Gtrr | Opcode::Eqir | Opcode::Eqri | Opcode::Eqrr
+    )
+}
+
+/// Register inputs of an instruction.
+fn reads(inst: Instruction) -> Vec<usize> {
+    let (kind_a, kind_b) = inst.opcode.inputs();
+    let mut registers = vec![];
+    if kind_a == Operand::Register {
+        registers.push(inst.a);
+    }
+    if kind_b == Operand::Register {
+        registers.push(inst.b);
+    }
+    registers
+}
+
+/// Names an input; reading the ip register always yields the instruction's own index.
+fn input(kind: Operand, value: usize, index: usize, ip: usize) -> String {
+    match kind {
+        Operand::Register if value == ip => index.to_string(),
+        Operand::Register => format!("r{}", value),
+        _ => value.to_string(),
+    }
+}
+
+fn condition(inst: Instruction, index: usize, ip: usize) -> Condition {
+    let (kind_a, kind_b) = inst.opcode.inputs();
+    let op = if matches!(inst.opcode, Opcode::Gtir | Opcode::Gtri | Opcode::Gtrr) {
+        ">"
+    } else {
+        "=="
+    };
+    Condition {
+        left: input(kind_a, inst.a, index, ip),
+        op,
+        right: input(kind_b, inst.b, index, ip),
+    }
+}
+
+/// The right-hand side of the instruction as an expression.
+fn expression(inst: Instruction, index: usize, ip: usize) -> String {
+    let (kind_a, kind_b) = inst.opcode.inputs();
+    let a = input(kind_a, inst.a, index, ip);
+    let b = input(kind_b, inst.b, index, ip);
+    match inst.opcode {
+        Opcode::Addr | Opcode::Addi => format!("{} + {}", a, b),
+        Opcode::Mulr | Opcode::Muli => format!("{} * {}", a, b),
+        Opcode::Banr | Opcode::Bani => format!("{} & {}", a, b),
+        Opcode::Borr | Opcode::Bori => format!("{} | {}", a, b),
+        Opcode::Setr | Opcode::Seti => a,
+        _ => format!("({})", condition(inst, index, ip)),
+    }
+}
+
+fn assignment(inst: Instruction, index: usize, ip: usize) -> String {
+    let (kind_a, kind_b) = inst.opcode.inputs();
+    let operator = match inst.opcode {
+        Opcode::Addr | Opcode::Addi => Some("+="),
+        Opcode::Mulr | Opcode::Muli => Some("*="),
+        Opcode::Banr | Opcode::Bani => Some("&="),
+        Opcode::Borr | Opcode::Bori => Some("|="),
+        _ => None,
+    };
+    // These operators commute, so either register input can be the one being updated.
+    let other = match operator {
+        Some(_) if kind_a == Operand::Register && inst.a == inst.c => {
+            Some(input(kind_b, inst.b, index, ip))
+        }
+        Some(_) if kind_b == Operand::Register && inst.b == inst.c => {
+            Some(input(kind_a, inst.a, index, ip))
+        }
+        _ => None,
+    };
+    match (operator, other) {
+        (Some(operator), Some(other)) => format!("r{} {} {}", inst.c, operator, other),
+        _ => format!("r{} = {}", inst.c, expression(inst, index, ip)),
+    }
+}
+
+/// The value an instruction computes when its only register input is ip, or none at all.
+fn constant(inst: Instruction, index: usize, ip: usize) -> Option<usize> {
+    let (kind_a, kind_b) = inst.opcode.inputs();
+    let value = |kind, v| match kind {
+        Operand::Register if v == ip => Some(index),
+        Operand::Register => None,
+        _ => Some(v),
+    };
+    let a = value(kind_a, inst.a)?;
+    let b = if kind_b == Operand::Unused {
+        0
+    } else {
+        value(kind_b, inst.b)?
+    };
+    match inst.opcode {
+        Opcode::Addr | Opcode::Addi => a.checked_add(b),
+        Opcode::Mulr | Opcode::Muli => a.checked_mul(b),
+        Opcode::Banr | Opcode::Bani => Some(a & b),
+        Opcode::Borr | Opcode::Bori => Some(a | b),
+        Opcode::Setr | Opcode::Seti => Some(a),
+        Opcode::Gtir | Opcode::Gtri | Opcode::Gtrr => Some((a > b) as usize),
+        Opcode::Eqir | Opcode::Eqri | Opcode::Eqrr => Some((a == b) as usize),
+    }
+}
+
+/// Resolves the jumps of a program bound to `ip`. A jump of the form `addr rX ip ip` right
+/// after a comparison into `rX` becomes a branch, and other jumps become gotos when their
+/// target does not depend on the registers.
+fn nodes(instructions: &[Instruction], ip: usize) -> Vec<Node> {
+    let mut nodes: Vec<Node> = instructions
+        .iter()
+        .enumerate()
+        .map(|(i, &inst)| {
+            if inst.c != ip {
+                Node::Assign(assignment(inst, i, ip))
+            } else if let Some(value) = constant(inst, i, ip) {
+                Node::Goto(value.saturating_add(1))
+            } else {
+                Node::Computed(format!("({}) + 1", expression(inst, i, ip)))
+            }
+        })
+        .collect();
+
+    let targets: Vec<usize> = nodes
+        .iter()
+        .filter_map(|node| match node {
+            Node::Goto(target) => Some(*target),
+            _ => None,
+        })
+        .collect();
+
+    let branches: Vec<usize> = (1..instructions.len())
+        .filter(|&i| {
+            let (test, jump) = (instructions[i - 1], instructions[i]);
+            let flag = if jump.a == ip { jump.b } else { jump.a };
+            jump.opcode == Opcode::Addr
+                && jump.c == ip
+                && (jump.a == ip || jump.b == ip)
+                && flag != ip
+                && is_comparison(test.opcode)
+                && test.c == flag
+                && !targets.contains(&i)
+        })
+        .collect();
+
+    // Instructions that can be reached other than by falling through from the one before.
+    let mut entries: HashSet<usize> = targets.iter().cloned().collect();
+    entries.insert(0);
+    for (i, inst) in instructions.iter().enumerate() {
+        if inst.c == ip {
+            entries.extend(&[i + 1, i + 2]);
+        }
+    }
+
+    for &i in &branches {
+        let test = instructions[i - 1];
+        let flag = test.c;
+        if flag_is_dead(instructions, &branches, &entries, ip, flag) {
+            nodes[i - 1] = Node::Nop;
+            nodes[i] = Node::Branch(condition(test, i - 1, ip), i + 2);
+        } else {
+            nodes[i] = Node::Branch(
+                Condition {
+                    left: format!("r{}", flag),
+                    op: "==",
+                    right: "1".to_string(),
+                },
+                i + 2,
+            );
+        }
+        // `if c skip; goto X` is a branch to X on the negated condition.
+        if let Some(&Node::Goto(target)) = nodes.get(i + 1) {
+            if !targets.contains(&(i + 1)) {
+                if let Node::Branch(condition, _) = &nodes[i] {
+                    nodes[i] = Node::Branch(condition.negate(), target);
+                }
+                nodes[i + 1] = Node::Nop;
+            }
+        }
+    }
+    nodes
+}
+
+/// Whether a comparison result in `flag` is only read by the branch right after the
+/// comparison, so it need not appear in the output. Every other read must follow a write to
+/// `flag` in the same straight run of instructions.
+fn flag_is_dead(
+    instructions: &[Instruction],
+    branches: &[usize],
+    entries: &HashSet<usize>,
+    ip: usize,
+    flag: usize,
+) -> bool {
+    instructions
+        .iter()
+        .enumerate()
+        .filter(|&(k, &inst)| !branches.contains(&k) && reads(inst).contains(&flag))
+        .all(|(k, _)| {
+            let mut s = k;
+            while s > 0 && !entries.contains(&s) {
+                s -= 1;
+                if instructions[s].c == flag {
+                    return true;
+                }
+                if instructions[s].c == ip {
+                    return false;
+                }
+            }
+            false
+        })
+}
+
+/// The loop head and exit of the innermost loop being structured.
+#[derive(Copy, Clone)]
+struct Context {
+    head: usize,
+    exit: usize,
+}
+
+struct Structurer {
+    nodes: Vec<Node>,
+    gotos: HashSet<usize>,
+}
+
+impl Structurer {
+    fn jumps_to(&self, from: usize, to: usize) -> bool {
+        matches!(self.nodes[from], Node::Goto(t) | Node::Branch(_, t) if t == to)
+    }
+
+    fn jump(&mut self, target: usize, context: Option<Context>) -> Stmt {
+        match context {
+            _ if target >= self.nodes.len() => Stmt::Halt,
+            Some(c) if c.head == target => Stmt::Continue,
+            Some(c) if c.exit == target => Stmt::Break,
+            _ => {
+                self.gotos.insert(target);
+                Stmt::Goto(target)
+            }
+        }
+    }
+
+    /// Structures instructions `lo..hi`. When `lo` is the head of the loop being structured,
+    /// its back edge is already accounted for.
+    fn structure(&mut self, lo: usize, hi: usize, context: Option<Context>) -> Vec<Stmt> {
+        let mut out = vec![];
+        let mut i = lo;
+        while i < hi {
+            let in_loop_body = i == lo && context.is_some_and(|c| c.head == lo);
+            if !in_loop_body {
+                out.push(Stmt::Label(i));
+                let back_edge = (i..hi).rev().find(|&j| self.jumps_to(j, i));
+                if let Some(j) = back_edge {
+                    out.push(self.structure_loop(i, j));
+                    i = j + 1;
+                    continue;
+                }
+            }
+            match self.nodes[i].clone() {
+                Node::Assign(text) => out.push(Stmt::Assign(text)),
+                Node::Computed(text) => out.push(Stmt::Computed(text)),
+                Node::Nop => {}
+                Node::Goto(target) => {
+                    if target != i + 1 {
+                        out.push(self.jump(target, context));
+                    }
+                }
+                Node::Branch(condition, target) if target > i && target <= hi => {
+                    // A then-branch ending in a forward jump past the target is an if/else.
+                    let otherwise_end = match self.nodes[target - 1] {
+                        Node::Goto(end) if target - 1 > i && end > target && end <= hi => Some(end),
+                        _ => None,
+                    };
+                    let stmt = match otherwise_end {
+                        Some(end) => {
+                            let then = self.structure(i + 1, target - 1, context);
+                            let otherwise = self.structure(target, end, context);
+                            i = end;
+                            Stmt::If {
+                                condition: condition.negate(),
+                                then,
+                                otherwise,
+                            }
+                        }
+                        None => {
+                            let then = self.structure(i + 1, target, context);
+                            i = target;
+                            Stmt::If {
+                                condition: condition.negate(),
+                                then,
+                                otherwise: vec![],
+                            }
+                        }
+                    };
+                    out.push(stmt);
+                    continue;
+                }
+                Node::Branch(condition, target) => {
+                    let jump = self.jump(target, context);
+                    out.push(Stmt::If {
+                        condition,
+                        then: vec![jump],
+                        otherwise: vec![],
+                    });
+                }
+            }
+            i += 1;
+        }
+        out
+    }
+
+    /// Structures the loop from `head` to its back edge at `tail`.
+    fn structure_loop(&mut self, head: usize, tail: usize) -> Stmt {
+        let context = Some(Context {
+            head,
+            exit: tail + 1,
+        });
+        let mut body = self.structure(head, tail, context);
+        if let Node::Branch(condition, _) = self.nodes[tail].clone() {
+            return Stmt::DoWhile(body, condition);
+        }
+        // A loop that starts by testing its exit condition is a while loop.
+        if let Some(Stmt::If {
+            condition,
+            then,
+            otherwise,
+        }) = body.first()
+        {
+            if then == &[Stmt::Break] && otherwise.is_empty() {
+                let condition = condition.negate();
+                body.remove(0);
+                return Stmt::While(condition, body);
+            }
+        }
+        Stmt::Loop(body)
+    }
+}
+
+/// Decompiles an `#ip`-bound program into structured pseudo-code. Jumps that do not fit a
+/// loop or conditional are kept as `goto`s to labelled instructions.
+pub fn decompile(program: &Program) -> Vec<Stmt> {
+    let instructions = &program.instructions;
+    let nodes = match program.ip {
+        Some(ip) => nodes(instructions, ip),
+        // Without a binding the program runs straight through.
+        None => instructions
+            .iter()
+            .enumerate()
+            .map(|(i, &inst)| Node::Assign(assignment(inst, i, usize::MAX)))
+            .collect(),
+    };
+    let mut structurer = Structurer {
+        nodes,
+        gotos: HashSet::new(),
+    };
+    let mut stmts = structurer.structure(0, instructions.len(), None);
+    strip_labels(&mut stmts, &structurer.gotos);
+    stmts
+}
+
+fn strip_labels(stmts: &mut Vec<Stmt>, keep: &HashSet<usize>) {
+    stmts.retain(|stmt| !matches!(stmt, Stmt::Label(n) if !keep.contains(n)));
+    for stmt in stmts {
+        match stmt {
+            Stmt::If {
+                then, otherwise, ..
+            } => {
+                strip_labels(then, keep);
+                strip_labels(otherwise, keep);
+            }
+            Stmt::Loop(body) | Stmt::While(_, body) | Stmt::DoWhile(body, _) => {
+                strip_labels(body, keep)
+            }
+            _ => {}
+        }
+    }
+}
+
+fn render_block(stmts: &[Stmt], depth: usize, out: &mut String) {
+    let indent = "    ".repeat(depth);
+    for stmt in stmts {
+        match stmt {
+            Stmt::Label(n) => out.push_str(&format!("{}L{}:\n", indent, n)),
+            Stmt::Assign(text) => out.push_str(&format!("{}{}\n", indent, text)),
+            Stmt::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                out.push_str(&format!("{}if {} {{\n", indent, condition));
+                render_block(then, depth + 1, out);
+                if !otherwise.is_empty() {
+                    out.push_str(&format!("{}}} else {{\n", indent));
+                    render_block(otherwise, depth + 1, out);
+                }
+                out.push_str(&format!("{}}}\n", indent));
+            }
+            Stmt::Loop(body) => {
+                out.push_str(&format!("{}loop {{\n", indent));
+                render_block(body, depth + 1, out);
+                out.push_str(&format!("{}}}\n", indent));
+            }
+            Stmt::While(condition, body) => {
+                out.push_str(&format!("{}while {} {{\n", indent, condition));
+                render_block(body, depth + 1, out);
+                out.push_str(&format!("{}}}\n", indent));
+            }
+            Stmt::DoWhile(body, condition) => {
+                out.push_str(&format!("{}do {{\n", indent));
+                render_block(body, depth + 1, out);
+                out.push_str(&format!("{}}} while {}\n", indent, condition));
+            }
+            Stmt::Break => out.push_str(&format!("{}break\n", indent)),
+            Stmt::Continue => out.push_str(&format!("{}continue\n", indent)),
+            Stmt::Goto(n) => out.push_str(&format!("{}goto L{}\n", indent, n)),
+            Stmt::Computed(text) => out.push_str(&format!("{}goto {}\n", indent, text)),
+            Stmt::Halt => out.push_str(&format!("{}halt\n", indent)),
+        }
+    }
+}
+
+/// Prints decompiled statements with four-space indentation.
+pub fn render(stmts: &[Stmt]) -> String {
+    let mut out = String::new();
+    render_block(stmts, 0, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decompiled(text: &str) -> String {
+        render(&decompile(&Program::parse(text).unwrap()))
+    }
+
+    #[test]
+    fn test_while_loops() {
+        // The nested divisor loop of the day 19 puzzle.
+        let text = "#ip 3
+        seti 10 0 4
+        seti 1 0 5
+outer:  seti 1 0 2
+inner:  mulr 5 2 1
+        eqrr 1 4 1
+        addr 1 3 3
+        addi 3 1 3
+        addr 5 0 0
+        addi 2 1 2
+        gtrr 2 4 1
+        addr 3 1 3
+        seti inner-1 0 3
+        addi 5 1 5
+        gtrr 5 4 1
+        addr 1 3 3
+        seti outer-1 0 3";
+        assert_eq!(
+            decompiled(text),
+            "r4 = 10
+r5 = 1
+do {
+    r2 = 1
+    do {
+        r1 = r5 * r2
+        if r1 == r4 {
+            r0 += r5
+        }
+        r2 += 1
+    } while r2 <= r4
+    r5 += 1
+} while r5 <= r4
+"
+        );
+    }
+
+    #[test]
+    fn test_if_else_and_halt() {
+        let text = "#ip 1
+        gtri 0 5 2
+        addr 2 1 1
+        seti else-1 0 1
+        addi 3 1 3
+        seti end-1 0 1
+else:   addi 4 1 4
+end:    eqri 3 1 2
+        addr 1 2 1
+        mulr 1 1 1
+        seti 0 0 0";
+        assert_eq!(
+            decompiled(text),
+            "if r0 > 5 {
+    r3 += 1
+} else {
+    r4 += 1
+}
+if r3 != 1 {
+    halt
+}
+r0 = 0
+"
+        );
+    }
+
+    #[test]
+    fn test_breaks_and_gotos() {
+        // The division loop of the day 21 puzzle.
+        let text = "#ip 1
+        seti 1000 0 4
+        seti 0 0 2
+loop:   addi 2 1 3
+        muli 3 256 3
+        gtrr 3 4 3
+        addr 3 1 1
+        addi 1 1 1
+        seti done-1 0 1
+        addi 2 1 2
+        seti loop-1 0 1
+done:   setr 2 0 5";
+        assert_eq!(
+            decompiled(text),
+            "r4 = 1000
+r2 = 0
+loop {
+    r3 = r2 + 1
+    r3 *= 256
+    if r3 > r4 {
+        break
+    }
+    r2 += 1
+}
+r5 = r2
+"
+        );
+
+        let text = "#ip 0
+        seti target-1 0 0
+        seti 1 0 1
+target: addr 1 0 0
+        seti 2 0 2";
+        assert_eq!(
+            decompiled(text),
+            "goto L2
+r1 = 1
+L2:
+goto (r1 + 2) + 1
+r2 = 2
+"
+        );
+    }
+
+    #[test]
+    fn test_straight_line() {
+        assert_eq!(
+            decompiled("seti 3 0 1\nmulr 1 1 2"),
+            "r1 = 3\nr2 = r1 * r1\n"
+        );
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+
+use super::{Instruction, Opcode, Operand};
+use crate::error::{Error, Result};
+
+/// Turns raw numeric instructions into `Instruction`s using a solved number to opcode mapping.
+pub fn decode(
+    program: &[Vec<usize>],
+    mapping: &HashMap<usize, Opcode>,
+) -> Result<Vec<Instruction>> {
+    program
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            let &opcode = mapping.get(&line[0]).ok_or_else(|| {
+                Error::InvalidProgram(format!(
+                    "instruction {} uses opcode number {}, which no sample identifies",
+                    i, line[0]
+                ))
+            })?;
+            Ok(Instruction {
+                opcode,
+                a: line[1],
+                b: line[2],
+                c: line[3],
+            })
+        })
+        .collect()
+}
+
+/// Names registers in listings: `r3`, or `ip` for the register bound to the instruction pointer.
+fn register(r: usize, ip: Option<usize>) -> String {
+    if Some(r) == ip {
+        "ip".to_string()
+    } else {
+        format!("r{}", r)
+    }
+}
+
+fn operand(kind: Operand, value: usize, ip: Option<usize>) -> Option<String> {
+    match kind {
+        Operand::Register => Some(register(value, ip)),
+        Operand::Immediate => Some(value.to_string()),
+        Operand::Unused => None,
+    }
+}
+
+/// Assembly with operand kinds spelled out, e.g. `addi r1 5 -> r3`. Unused inputs are omitted.
+pub fn format_instruction(inst: Instruction, ip: Option<usize>) -> String {
+    let (kind_a, kind_b) = inst.opcode.inputs();
+    let mut parts = vec![inst.opcode.name().to_string()];
+    parts.extend(operand(kind_a, inst.a, ip));
+    parts.extend(operand(kind_b, inst.b, ip));
+    format!("{} -> {}", parts.join(" "), register(inst.c, ip))
+}
+
+/// The instruction as an assignment, e.g. `r3 = r1 + 5`.
+pub fn pseudo_code(inst: Instruction, ip: Option<usize>) -> String {
+    let (kind_a, kind_b) = inst.opcode.inputs();
+    let a = operand(kind_a, inst.a, ip).unwrap_or_default();
+    let b = operand(kind_b, inst.b, ip).unwrap_or_default();
+    let expression = match inst.opcode {
+        Opcode::Addr | Opcode::Addi => format!("{} + {}", a, b),
+        Opcode::Mulr | Opcode::Muli => format!("{} * {}", a, b),
+        Opcode::Banr | Opcode::Bani => format!("{} & {}", a, b),
+        Opcode::Borr | Opcode::Bori => format!("{} | {}", a, b),
+        Opcode::Setr | Opcode::Seti => a,
+        Opcode::Gtir | Opcode::Gtri | Opcode::Gtrr => format!("({} > {})", a, b),
+        Opcode::Eqir | Opcode::Eqri | Opcode::Eqrr => format!("({} == {})", a, b),
+    };
+    format!("{} = {}", register(inst.c, ip), expression)
+}
+
+/// A numbered listing with the assembly and pseudo-code side by side.
+pub fn disassemble(instructions: &[Instruction], ip: Option<usize>) -> String {
+    let lines: Vec<(String, String)> = instructions
+        .iter()
+        .map(|&inst| (format_instruction(inst, ip), pseudo_code(inst, ip)))
+        .collect();
+    let number_width = instructions.len().saturating_sub(1).to_string().len();
+    let asm_width = lines.iter().map(|(asm, _)| asm.len()).max().unwrap_or(0);
+
+    let mut out = String::new();
+    if let Some(r) = ip {
+        out.push_str(&format!("#ip {}\n", r));
+    }
+    for (i, (asm, pseudo)) in lines.iter().enumerate() {
+        out.push_str(&format!(
+            "{:>nw$}: {:<aw$}  ; {}\n",
+            i,
+            asm,
+            pseudo,
+            nw = number_width,
+            aw = asm_width
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inst(opcode: Opcode, a: usize, b: usize, c: usize) -> Instruction {
+        Instruction { opcode, a, b, c }
+    }
+
+    #[test]
+    fn test_decode() {
+        let mapping: HashMap<usize, Opcode> = vec![(0, Opcode::Addi), (7, Opcode::Seti)]
+            .into_iter()
+            .collect();
+        let program = vec![vec![7, 5, 0, 1], vec![0, 1, 5, 3]];
+        assert_eq!(
+            decode(&program, &mapping),
+            Ok(vec![
+                inst(Opcode::Seti, 5, 0, 1),
+                inst(Opcode::Addi, 1, 5, 3)
+            ])
+        );
+        assert!(decode(&[vec![3, 0, 0, 0]], &mapping).is_err());
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(
+            format_instruction(inst(Opcode::Addi, 1, 5, 3), None),
+            "addi r1 5 -> r3"
+        );
+        assert_eq!(
+            format_instruction(inst(Opcode::Seti, 7, 9, 0), None),
+            "seti 7 -> r0"
+        );
+        assert_eq!(
+            format_instruction(inst(Opcode::Gtir, 7, 2, 0), None),
+            "gtir 7 r2 -> r0"
+        );
+        assert_eq!(
+            pseudo_code(inst(Opcode::Addi, 1, 5, 3), None),
+            "r3 = r1 + 5"
+        );
+        assert_eq!(pseudo_code(inst(Opcode::Setr, 2, 9, 1), None), "r1 = r2");
+        assert_eq!(
+            pseudo_code(inst(Opcode::Eqri, 2, 9, 1), None),
+            "r1 = (r2 == 9)"
+        );
+        assert_eq!(
+            pseudo_code(inst(Opcode::Addr, 4, 0, 0), Some(0)),
+            "ip = r4 + ip"
+        );
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let program = vec![inst(Opcode::Seti, 5, 0, 1), inst(Opcode::Mulr, 1, 1, 2)];
+        assert_eq!(
+            disassemble(&program, None),
+            "0: seti 5 -> r1      ; r1 = 5\n1: mulr r1 r1 -> r2  ; r2 = r1 * r1\n"
+        );
+        assert!(disassemble(&program, Some(3)).starts_with("#ip 3\n"));
+    }
+}
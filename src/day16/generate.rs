@@ -0,0 +1,310 @@
+use super::{which_opcodes_work, Fixture, Instruction, Opcode, Operand, CPU};
+use crate::error::{Error, Result};
+use crate::utils::Rng;
+
+/// A secret assignment of opcodes to numbers: number `n` stands for `mapping[n]`.
+pub type Mapping = [Opcode; 16];
+
+/// How `generate` builds a puzzle input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    /// Number of `Before:`/`After:` samples.
+    pub samples: usize,
+    /// Number of instructions in the test program.
+    pub program_len: usize,
+    /// Registers and immediates are drawn from `0..=max_value`.
+    pub max_value: usize,
+    /// Numbers that get no samples, so the samples cannot tell what they stand for.
+    pub unsampled: Vec<usize>,
+    /// Each sample is the most ambiguous of this many random ones, judged by how many opcodes
+    /// it fits. Higher values make elimination harder; 1 picks samples uniformly.
+    pub ambiguity: usize,
+}
+
+impl Default for Options {
+    /// About the size of a real puzzle input.
+    fn default() -> Self {
+        Options {
+            samples: 800,
+            program_len: 900,
+            max_value: 3,
+            unsampled: vec![],
+            ambiguity: 1,
+        }
+    }
+}
+
+/// A generated input and what the test program leaves in register 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Generated {
+    pub text: String,
+    pub register0: usize,
+}
+
+pub fn shuffled_mapping(rng: &mut Rng) -> Mapping {
+    let mut mapping = Opcode::ALL;
+    rng.shuffle(&mut mapping);
+    mapping
+}
+
+/// Parses sixteen comma-separated opcode names, the one for number 0 first.
+pub fn parse_mapping(text: &str) -> Result<Mapping> {
+    let names: Vec<&str> = text.split(',').map(str::trim).collect();
+    if names.len() != 16 {
+        return Err(Error::InvalidProgram(format!(
+            "a mapping needs 16 opcodes, found {}",
+            names.len()
+        )));
+    }
+    let mut mapping = Opcode::ALL;
+    for (slot, name) in mapping.iter_mut().zip(names) {
+        *slot = Opcode::from_name(name)
+            .ok_or_else(|| Error::InvalidProgram(format!("unknown opcode {:?}", name)))?;
+    }
+    if let Some(missing) = Opcode::ALL.iter().find(|op| !mapping.contains(op)) {
+        return Err(Error::InvalidProgram(format!(
+            "the mapping does not use {}",
+            missing.name()
+        )));
+    }
+    Ok(mapping)
+}
+
+fn operand(rng: &mut Rng, kind: Operand, max_value: usize) -> usize {
+    match kind {
+        Operand::Register => rng.below(4),
+        _ => rng.below(max_value.saturating_add(1)),
+    }
+}
+
+fn random_instruction(rng: &mut Rng, opcode: Opcode, max_value: usize) -> Instruction {
+    let (kind_a, kind_b) = opcode.inputs();
+    Instruction {
+        opcode,
+        a: operand(rng, kind_a, max_value),
+        b: operand(rng, kind_b, max_value),
+        c: rng.below(4),
+    }
+}
+
+/// A random sample of `number` at work.
+fn sample(rng: &mut Rng, mapping: &Mapping, number: usize, max_value: usize) -> Fixture {
+    loop {
+        let before = CPU::new([0; 4].map(|_| rng.below(max_value.saturating_add(1))));
+        let inst = random_instruction(rng, mapping[number], max_value);
+        if let Ok(after) = before.execute(inst) {
+            return (before, vec![number, inst.a, inst.b, inst.c], after);
+        }
+    }
+}
+
+fn registers(cpu: &CPU) -> String {
+    let values: Vec<String> = cpu.registers().iter().map(|r| r.to_string()).collect();
+    values.join(", ")
+}
+
+/// Generates a day 16 input for `mapping` in the puzzle's format, which `Day16::parse` reads.
+/// The same mapping, seed and options always give the same input. Program instructions that
+/// would overflow are redrawn, so the program always runs to the end.
+pub fn generate(mapping: &Mapping, seed: u64, options: &Options) -> Generated {
+    let mut rng = Rng::new(seed);
+    let max_value = options.max_value;
+    let sampled: Vec<usize> = (0..16).filter(|n| !options.unsampled.contains(n)).collect();
+    let mut fixtures = vec![];
+    if !sampled.is_empty() {
+        for i in 0..options.samples {
+            // Every sampled number shows up at least once when there are enough samples.
+            let number = match sampled.get(i) {
+                Some(&n) => n,
+                None => sampled[rng.below(sampled.len())],
+            };
+            let candidates: Vec<Fixture> = (0..options.ambiguity.max(1))
+                .map(|_| sample(&mut rng, mapping, number, max_value))
+                .collect();
+            let most_ambiguous = candidates
+                .into_iter()
+                .max_by_key(|fixture| {
+                    which_opcodes_work(fixture.clone(), Opcode::ALL.to_vec()).len()
+                })
+                .unwrap();
+            fixtures.push(most_ambiguous);
+        }
+    }
+    rng.shuffle(&mut fixtures);
+
+    let mut cpu = CPU::new([0; 4]);
+    let mut program = vec![];
+    while program.len() < options.program_len {
+        let number = rng.below(16);
+        let inst = random_instruction(&mut rng, mapping[number], max_value);
+        if let Ok(next) = cpu.execute(inst) {
+            cpu = next;
+            program.push(format!("{} {} {} {}", number, inst.a, inst.b, inst.c));
+        }
+    }
+
+    let samples: Vec<String> = fixtures
+        .iter()
+        .map(|(before, inst, after)| {
+            format!(
+                "Before: [{}]\n{} {} {} {}\nAfter:  [{}]",
+                registers(before),
+                inst[0],
+                inst[1],
+                inst[2],
+                inst[3],
+                registers(after)
+            )
+        })
+        .collect();
+    Generated {
+        text: format!("{}\n\n\n\n{}\n", samples.join("\n\n"), program.join("\n")),
+        register0: cpu.registers()[0],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::day16::{candidates, infer_opcodes, part2, Day16};
+    use crate::solution::Solution;
+
+    fn secret() -> Mapping {
+        shuffled_mapping(&mut Rng::new(7))
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mapping = secret();
+        let options = Options {
+            samples: 300,
+            program_len: 200,
+            ..Options::default()
+        };
+        let generated = generate(&mapping, 1, &options);
+        assert!(generated.text.starts_with("Before: ["));
+        assert!(generated.text.contains("]\n\n\n\n"));
+        assert!(generated.text.ends_with('\n'));
+
+        let (fixtures, program) = Day16.parse(&generated.text).unwrap();
+        assert_eq!(fixtures.len(), 300);
+        assert_eq!(program.len(), 200);
+        for fixture in &fixtures {
+            let number = fixture.1[0];
+            assert!(which_opcodes_work(fixture.clone(), vec![mapping[number]]).len() == 1);
+        }
+        let known = infer_opcodes(&fixtures).unwrap();
+        assert!((0..16).all(|n| known[&n] == mapping[n]));
+        assert_eq!(
+            part2(&fixtures, &program).unwrap().registers()[0],
+            generated.register0
+        );
+
+        assert_eq!(generate(&mapping, 1, &options), generated);
+        assert_ne!(generate(&mapping, 2, &options).text, generated.text);
+    }
+
+    #[test]
+    fn test_large_values_crlf() {
+        let mapping = secret();
+        let options = Options {
+            samples: 200,
+            program_len: 100,
+            max_value: 5000,
+            ..Options::default()
+        };
+        let generated = generate(&mapping, 3, &options);
+        let crlf = generated.text.replace('\n', "\r\n");
+        let (fixtures, program) = Day16.parse(&crlf).unwrap();
+        assert_eq!(
+            Day16.parse(&generated.text).unwrap(),
+            (fixtures.clone(), program.clone())
+        );
+        assert!(fixtures
+            .iter()
+            .any(|f| f.0.registers().iter().any(|&r| r > 9)));
+        assert_eq!(
+            part2(&fixtures, &program).unwrap().registers()[0],
+            generated.register0
+        );
+    }
+
+    #[test]
+    fn test_under_determined() {
+        let mapping = secret();
+        // Once the other fifteen numbers are pinned down, the last one takes the opcode left.
+        let options = Options {
+            samples: 400,
+            unsampled: vec![15],
+            ..Options::default()
+        };
+        let generated = generate(&mapping, 5, &options);
+        let (fixtures, program) = Day16.parse(&generated.text).unwrap();
+        assert!(!candidates(&fixtures).contains_key(&15));
+        let known = infer_opcodes(&fixtures).unwrap();
+        assert!((0..16).all(|n| known[&n] == mapping[n]));
+        assert_eq!(
+            part2(&fixtures, &program).unwrap().registers()[0],
+            generated.register0
+        );
+
+        // Two unsampled numbers could stand for either of the two opcodes left.
+        let options = Options {
+            samples: 400,
+            unsampled: vec![3, 11],
+            ..Options::default()
+        };
+        let (fixtures, program) = Day16.parse(&generate(&mapping, 5, &options).text).unwrap();
+        let candidates = candidates(&fixtures);
+        assert_eq!(candidates.len(), 14);
+        assert!(!candidates.contains_key(&3) && !candidates.contains_key(&11));
+        let left: Vec<&str> = Opcode::ALL
+            .iter()
+            .filter(|op| **op == mapping[3] || **op == mapping[11])
+            .map(|op| op.name())
+            .collect();
+        let expected = Error::InvalidProgram(format!(
+            "the samples do not pin down every opcode: 3 could be {0}, 11 could be {0}",
+            left.join("/")
+        ));
+        assert_eq!(infer_opcodes(&fixtures), Err(expected.clone()));
+        assert_eq!(part2(&fixtures, &program), Err(expected));
+    }
+
+    #[test]
+    fn test_ambiguity() {
+        let mapping = secret();
+        let average_fit = |ambiguity| {
+            let options = Options {
+                samples: 200,
+                program_len: 1,
+                ambiguity,
+                ..Options::default()
+            };
+            let (fixtures, _) = Day16.parse(&generate(&mapping, 9, &options).text).unwrap();
+            let total: usize = fixtures
+                .iter()
+                .map(|f| which_opcodes_work(f.clone(), Opcode::ALL.to_vec()).len())
+                .sum();
+            total as f64 / fixtures.len() as f64
+        };
+        assert!(average_fit(8) > average_fit(1) + 1.0);
+    }
+
+    #[test]
+    fn test_parse_mapping() {
+        let mapping = secret();
+        let names: Vec<&str> = mapping.iter().map(|op| op.name()).collect();
+        assert_eq!(parse_mapping(&names.join(", ")), Ok(mapping));
+        assert!(parse_mapping("addr, addi").is_err());
+        let mut twice = names.clone();
+        twice[0] = twice[1];
+        assert_eq!(
+            parse_mapping(&twice.join(",")),
+            Err(Error::InvalidProgram(format!(
+                "the mapping does not use {}",
+                mapping[0].name()
+            )))
+        );
+    }
+}
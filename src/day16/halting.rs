@@ -0,0 +1,159 @@
+use std::collections::HashSet;
+
+use super::optimize;
+use super::program::{Machine, Program};
+use super::{Opcode, Operand};
+use crate::error::Result;
+
+/// The values a register was compared against while a program ran.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watch {
+    /// Every distinct value in the order first seen.
+    pub values: Vec<usize>,
+    /// Whether a value came round again. Programs that halt on a match keep cycling through
+    /// the same values after that, so no later value can be new.
+    pub cycled: bool,
+    pub steps: u64,
+}
+
+impl Watch {
+    /// The value that halts the program soonest when placed in the register.
+    pub fn first(&self) -> Option<usize> {
+        self.values.first().cloned()
+    }
+
+    /// The value that halts the program latest, if the values cycled.
+    pub fn last(&self) -> Option<usize> {
+        if self.cycled {
+            self.values.last().cloned()
+        } else {
+            None
+        }
+    }
+}
+
+/// What a comparison compares `register` against, if it involves the register at all.
+fn compared_value<const N: usize>(
+    opcode: Opcode,
+    a: usize,
+    b: usize,
+    register: usize,
+    machine: &Machine<N>,
+    ip: Option<usize>,
+) -> Option<usize> {
+    if !matches!(
+        opcode,
+        Opcode::Gtir | Opcode::Gtri | Opcode::Gtrr | Opcode::Eqir | Opcode::Eqri | Opcode::Eqrr
+    ) {
+        return None;
+    }
+    let value = |kind, v: usize| match kind {
+        // The bound register holds the instruction pointer while the instruction runs.
+        Operand::Register if Some(v) == ip => Some(machine.ip),
+        Operand::Register => machine.registers().get(v).cloned(),
+        _ => Some(v),
+    };
+    let (kind_a, kind_b) = opcode.inputs();
+    match (
+        kind_a == Operand::Register && a == register,
+        kind_b == Operand::Register && b == register,
+    ) {
+        (true, false) => value(kind_b, b),
+        (false, true) => value(kind_a, a),
+        _ => None,
+    }
+}
+
+/// Runs `program` and records every value compared against `register` until one repeats,
+/// the program halts, or `max_steps` instructions have run. Loops the optimizer recognises
+/// are shortcut, so long-running programs finish quickly.
+pub fn watch_register<const N: usize>(
+    program: &Program,
+    machine: &mut Machine<N>,
+    register: usize,
+    max_steps: u64,
+) -> Result<Watch> {
+    let shortcuts = optimize::optimize(program);
+    let mut values = vec![];
+    let mut seen = HashSet::new();
+    while machine.steps() < max_steps {
+        if let Some(&inst) = program.instructions.get(machine.ip) {
+            let value = compared_value(inst.opcode, inst.a, inst.b, register, machine, program.ip);
+            if let Some(value) = value {
+                if !seen.insert(value) {
+                    return Ok(Watch {
+                        values,
+                        cycled: true,
+                        steps: machine.steps(),
+                    });
+                }
+                values.push(value);
+            }
+        }
+        if !machine.step_optimized(program, &shortcuts)? {
+            break;
+        }
+    }
+    Ok(Watch {
+        values,
+        cycled: false,
+        steps: machine.steps(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Cycles x through all 32 values of ((x + 7) * 5) & 31, offset by 32 so x never equals
+    // the initial r0, and halts once x == r0.
+    const PROGRAM: &str = "#ip 5
+        seti 0 0 3
+loop:   addi 3 7 3
+        muli 3 5 3
+        bani 3 31 3
+        bori 3 32 3
+        eqrr 3 0 2
+        addr 2 5 5
+        seti loop-1 0 5";
+
+    fn steps_to_halt(program: &Program, r0: usize) -> u64 {
+        let mut machine: Machine = Machine::new(program).unwrap();
+        machine.cpu.registers_mut()[0] = r0;
+        machine.run(program).unwrap()
+    }
+
+    #[test]
+    fn test_watch_register() {
+        let program = Program::parse(PROGRAM).unwrap();
+        let mut machine: Machine = Machine::new(&program).unwrap();
+        let watch = watch_register(&program, &mut machine, 0, 1_000_000).unwrap();
+        assert!(watch.cycled);
+        assert_eq!(watch.values.len(), 32);
+        assert_eq!(watch.first(), Some(35));
+
+        let first = steps_to_halt(&program, watch.first().unwrap());
+        let last = steps_to_halt(&program, watch.last().unwrap());
+        assert_eq!(first, 7);
+        assert_eq!(last, 7 * 32);
+        for &value in &watch.values {
+            let steps = steps_to_halt(&program, value);
+            assert!(first <= steps && steps <= last);
+        }
+    }
+
+    #[test]
+    fn test_no_cycle() {
+        let program = Program::parse(PROGRAM).unwrap();
+        let mut machine: Machine = Machine::new(&program).unwrap();
+        let watch = watch_register(&program, &mut machine, 0, 50).unwrap();
+        assert!(!watch.cycled);
+        assert_eq!(watch.last(), None);
+        assert_eq!(watch.steps, 50);
+
+        // Comparisons that do not involve the register are ignored.
+        let mut machine: Machine = Machine::new(&program).unwrap();
+        let watch = watch_register(&program, &mut machine, 4, 1000).unwrap();
+        assert!(watch.values.is_empty());
+    }
+}
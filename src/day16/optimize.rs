@@ -0,0 +1,460 @@
+use std::collections::HashMap;
+
+use super::program::Program;
+use super::{Arithmetic, Instruction, Opcode};
+
+/// A loop the optimizer knows how to compute directly. Register fields name the registers
+/// the loop uses; `exit` is the instruction the loop leaves to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Shortcut {
+    /// Counts `counter` up from its current value until it passes `target`, adding `factor`
+    /// to `sum` whenever `factor * counter == target`:
+    ///
+    /// ```text
+    /// mulr factor counter scratch
+    /// eqrr scratch target scratch
+    /// addr scratch ip ip
+    /// addi ip 1 ip
+    /// addr factor sum sum
+    /// addi counter 1 counter
+    /// gtrr counter target scratch
+    /// addr ip scratch ip
+    /// seti <loop start - 1> 0 ip
+    /// ```
+    ///
+    /// Run for every `factor`, this sums the divisors of `target`.
+    DivisorSum {
+        factor: usize,
+        counter: usize,
+        target: usize,
+        sum: usize,
+        scratch: usize,
+        exit: usize,
+    },
+    /// Counts `quotient` up until `(quotient + 1) * divisor > dividend`, which divides
+    /// `dividend` by the immediate `divisor`:
+    ///
+    /// ```text
+    /// addi quotient 1 scratch
+    /// muli scratch <divisor> scratch
+    /// gtrr scratch dividend scratch
+    /// addr scratch ip ip
+    /// addi ip 1 ip
+    /// seti <exit - 1> 0 ip
+    /// addi quotient 1 quotient
+    /// seti <loop start - 1> 0 ip
+    /// ```
+    Divide {
+        quotient: usize,
+        divisor: usize,
+        dividend: usize,
+        scratch: usize,
+        exit: usize,
+    },
+}
+
+fn commutative(inst: Instruction, opcode: Opcode, x: usize, y: usize, c: usize) -> bool {
+    inst.opcode == opcode
+        && inst.c == c
+        && ((inst.a == x && inst.b == y) || (inst.a == y && inst.b == x))
+}
+
+fn exact(inst: Instruction, opcode: Opcode, a: usize, b: usize, c: usize) -> bool {
+    inst.opcode == opcode && inst.a == a && inst.b == b && inst.c == c
+}
+
+fn distinct(registers: &[usize]) -> bool {
+    registers
+        .iter()
+        .enumerate()
+        .all(|(i, r)| !registers[i + 1..].contains(r))
+}
+
+fn divisor_sum(code: &[Instruction], head: usize, ip: usize) -> Option<Shortcut> {
+    let code = code.get(head..head + 9)?;
+    let multiply = code[0];
+    if multiply.opcode != Opcode::Mulr {
+        return None;
+    }
+    let counter = code[5].a;
+    let factor = if multiply.b == counter {
+        multiply.a
+    } else {
+        multiply.b
+    };
+    let scratch = multiply.c;
+    let target = if code[1].a == scratch {
+        code[1].b
+    } else {
+        code[1].a
+    };
+    let sum = code[4].c;
+    let matches = commutative(code[0], Opcode::Mulr, factor, counter, scratch)
+        && commutative(code[1], Opcode::Eqrr, scratch, target, scratch)
+        && commutative(code[2], Opcode::Addr, scratch, ip, ip)
+        && exact(code[3], Opcode::Addi, ip, 1, ip)
+        && commutative(code[4], Opcode::Addr, factor, sum, sum)
+        && exact(code[5], Opcode::Addi, counter, 1, counter)
+        && exact(code[6], Opcode::Gtrr, counter, target, scratch)
+        && commutative(code[7], Opcode::Addr, ip, scratch, ip)
+        && code[8].opcode == Opcode::Seti
+        && code[8].a.checked_add(1) == Some(head)
+        && code[8].c == ip
+        && distinct(&[factor, counter, target, sum, scratch, ip]);
+    if !matches {
+        return None;
+    }
+    Some(Shortcut::DivisorSum {
+        factor,
+        counter,
+        target,
+        sum,
+        scratch,
+        exit: head + 9,
+    })
+}
+
+fn divide(code: &[Instruction], head: usize, ip: usize) -> Option<Shortcut> {
+    let code = code.get(head..head + 8)?;
+    let quotient = code[0].a;
+    let scratch = code[0].c;
+    let divisor = code[1].b;
+    let dividend = code[2].b;
+    let exit = code[5].a.checked_add(1)?;
+    let matches = exact(code[0], Opcode::Addi, quotient, 1, scratch)
+        && exact(code[1], Opcode::Muli, scratch, divisor, scratch)
+        && exact(code[2], Opcode::Gtrr, scratch, dividend, scratch)
+        && commutative(code[3], Opcode::Addr, scratch, ip, ip)
+        && exact(code[4], Opcode::Addi, ip, 1, ip)
+        && code[5].opcode == Opcode::Seti
+        && code[5].c == ip
+        && exact(code[6], Opcode::Addi, quotient, 1, quotient)
+        && code[7].opcode == Opcode::Seti
+        && code[7].a.checked_add(1) == Some(head)
+        && code[7].c == ip
+        && distinct(&[quotient, dividend, scratch, ip]);
+    if !matches {
+        return None;
+    }
+    Some(Shortcut::Divide {
+        quotient,
+        divisor,
+        dividend,
+        scratch,
+        exit,
+    })
+}
+
+impl Shortcut {
+    /// Runs the whole loop on `registers`, as if entered at its first instruction, and
+    /// returns the instruction to continue at. Returns `None` without touching the registers
+    /// when the loop would not terminate, any of its instructions would overflow, a register
+    /// is missing, or `arithmetic` is not `Checked`, so the caller can fall back to
+    /// interpreting it and get the same result or error.
+    pub fn apply(
+        &self,
+        registers: &mut [usize],
+        ip: usize,
+        arithmetic: Arithmetic,
+    ) -> Option<usize> {
+        if arithmetic != Arithmetic::Checked {
+            return None;
+        }
+        let highest = match *self {
+            Shortcut::DivisorSum {
+                factor,
+                counter,
+                target,
+                sum,
+                scratch,
+                ..
+            } => factor.max(counter).max(target).max(sum).max(scratch),
+            Shortcut::Divide {
+                quotient,
+                dividend,
+                scratch,
+                ..
+            } => quotient.max(dividend).max(scratch),
+        };
+        if highest.max(ip) >= registers.len() {
+            return None;
+        }
+        let exit = match *self {
+            Shortcut::DivisorSum {
+                factor,
+                counter,
+                target,
+                sum,
+                scratch,
+                exit,
+            } => {
+                let (f, start, n) = (registers[factor], registers[counter], registers[target]);
+                // The body runs at least once, then until the counter passes the target.
+                let last = start.max(n);
+                // The product grows with the counter, so the last one is the largest.
+                f.checked_mul(last)?;
+                let hit = if f == 0 {
+                    n == 0
+                } else {
+                    n % f == 0 && n / f >= start
+                };
+                let total = if hit {
+                    registers[sum].checked_add(f)?
+                } else {
+                    registers[sum]
+                };
+                let next = last.checked_add(1)?;
+                registers[sum] = total;
+                registers[counter] = next;
+                registers[scratch] = 1;
+                exit
+            }
+            Shortcut::Divide {
+                quotient,
+                divisor,
+                dividend,
+                scratch,
+                exit,
+            } => {
+                if divisor == 0 {
+                    return None;
+                }
+                let (q, n) = (registers[quotient], registers[dividend]);
+                let done = q.checked_add(1)?.checked_mul(divisor)? > n;
+                let last = if done { q } else { n / divisor };
+                // The final comparison computes the largest product.
+                last.checked_add(1)?.checked_mul(divisor)?;
+                registers[quotient] = last;
+                registers[scratch] = 1;
+                exit
+            }
+        };
+        // The last jump of the loop leaves the instruction pointer register one short of the
+        // exit, as the pointer is incremented after every instruction.
+        registers[ip] = exit - 1;
+        Some(exit)
+    }
+}
+
+/// Finds the loops in a program that can be replaced by a `Shortcut`, keyed by the index of
+/// their first instruction. Only programs with an `#ip` binding can loop.
+pub fn optimize(program: &Program) -> HashMap<usize, Shortcut> {
+    let ip = match program.ip {
+        Some(ip) => ip,
+        None => return HashMap::new(),
+    };
+    (0..program.instructions.len())
+        .filter_map(|head| {
+            divisor_sum(&program.instructions, head, ip)
+                .or_else(|| divide(&program.instructions, head, ip))
+                .map(|shortcut| (head, shortcut))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::day16::program::Machine;
+    use crate::day16::CPU;
+    use crate::error::Error;
+
+    /// Sums the divisors of `n` into r0 with the nested loop of the day 19 puzzle.
+    fn sum_of_divisors(n: usize) -> Program {
+        Program::parse(&format!(
+            "#ip 3
+        seti {} 0 4
+        seti 1 0 5
+outer:  seti 1 0 2
+inner:  mulr 5 2 1
+        eqrr 1 4 1
+        addr 1 3 3
+        addi 3 1 3
+        addr 5 0 0
+        addi 2 1 2
+        gtrr 2 4 1
+        addr 3 1 3
+        seti inner-1 0 3
+        addi 5 1 5
+        gtrr 5 4 1
+        addr 1 3 3
+        seti outer-1 0 3",
+            n
+        ))
+        .unwrap()
+    }
+
+    /// Divides `n` by 256 into r2 with the counting loop of the day 21 puzzle.
+    fn divide_by_256(n: usize) -> Program {
+        Program::parse(&format!(
+            "#ip 1
+        seti {} 0 4
+        seti 0 0 2
+loop:   addi 2 1 3
+        muli 3 256 3
+        gtrr 3 4 3
+        addr 3 1 1
+        addi 1 1 1
+        seti done-1 0 1
+        addi 2 1 2
+        seti loop-1 0 1
+done:   setr 2 0 5",
+            n
+        ))
+        .unwrap()
+    }
+
+    fn run(program: &Program, optimized: bool) -> Machine {
+        let mut machine: Machine = Machine::new(program).unwrap();
+        if optimized {
+            machine.run_optimized(program, &optimize(program)).unwrap();
+        } else {
+            machine.run(program).unwrap();
+        }
+        machine
+    }
+
+    #[test]
+    fn test_detect() {
+        let program = sum_of_divisors(10);
+        let shortcuts = optimize(&program);
+        assert_eq!(shortcuts.len(), 1);
+        assert_eq!(
+            shortcuts[&3],
+            Shortcut::DivisorSum {
+                factor: 5,
+                counter: 2,
+                target: 4,
+                sum: 0,
+                scratch: 1,
+                exit: 12
+            }
+        );
+        assert!(matches!(
+            optimize(&divide_by_256(10))[&2],
+            Shortcut::Divide {
+                divisor: 256,
+                exit: 10,
+                ..
+            }
+        ));
+
+        let mut plain = program.clone();
+        plain.ip = None;
+        assert!(optimize(&plain).is_empty());
+    }
+
+    #[test]
+    fn test_same_result() {
+        for n in [0, 1, 12, 97, 120] {
+            let program = sum_of_divisors(n);
+            let plain = run(&program, false);
+            let fast = run(&program, true);
+            assert_eq!(
+                plain.registers(),
+                fast.registers(),
+                "sum of divisors of {}",
+                n
+            );
+            assert_eq!(plain.ip, fast.ip);
+            assert!(fast.steps() < plain.steps() || n < 2);
+        }
+        for n in [0, 255, 256, 1000, 65536] {
+            let program = divide_by_256(n);
+            let plain = run(&program, false);
+            let fast = run(&program, true);
+            assert_eq!(plain.registers(), fast.registers(), "{} / 256", n);
+            assert_eq!(fast.registers()[5], n / 256);
+        }
+    }
+
+    #[test]
+    fn test_overflow_left_to_interpreter() {
+        let divisors = sum_of_divisors(10);
+        let divide = divide_by_256(10);
+        let cases = [
+            // factor * counter overflows on the third pass through the inner loop.
+            (&divisors, 3, [0, 0, 1, 0, 10, usize::MAX / 2]),
+            // sum + factor overflows when the factor divides the target.
+            (&divisors, 3, [usize::MAX, 0, 1, 0, 10, 5]),
+            // quotient + 1 overflows straight away.
+            (&divide, 2, [0, 0, usize::MAX, 0, 10, 0]),
+            // (quotient + 1) * 256 overflows.
+            (&divide, 2, [0, 0, usize::MAX / 256, 0, 10, 0]),
+            // Only the last comparison overflows.
+            (&divide, 2, [0, 0, usize::MAX / 256 - 1, 0, usize::MAX, 0]),
+        ];
+        for (program, head, registers) in cases {
+            let mut after = registers;
+            let shortcut = optimize(program)[&head];
+            let ip = program.ip.unwrap();
+            assert_eq!(shortcut.apply(&mut after, ip, Arithmetic::Checked), None);
+            assert_eq!(after, registers);
+        }
+    }
+
+    /// Runs `program` from instruction `ip` with the given registers, plainly and with
+    /// shortcuts, and checks both end in the same registers or fail with the same error.
+    fn assert_agree(program: &Program, ip: usize, registers: [usize; 6], arithmetic: Arithmetic) {
+        let start = |mut machine: Machine| {
+            machine.cpu = CPU::new(registers).with_arithmetic(arithmetic);
+            machine.ip = ip;
+            machine
+        };
+        let mut plain = start(Machine::new(program).unwrap());
+        let mut fast = start(Machine::new(program).unwrap());
+        let plain_result = plain.run(program).map(|_| plain.registers().to_vec());
+        let fast_result = fast
+            .run_optimized(program, &optimize(program))
+            .map(|_| fast.registers().to_vec());
+        assert_eq!(
+            plain_result, fast_result,
+            "{:?} {:?}",
+            registers, arithmetic
+        );
+    }
+
+    #[test]
+    fn test_overflow() {
+        let modes = [
+            Arithmetic::Checked,
+            Arithmetic::Wrapping,
+            Arithmetic::Saturating,
+        ];
+        let divisors = sum_of_divisors(10);
+        let divide = divide_by_256(10);
+        for arithmetic in modes {
+            assert_agree(&divisors, 3, [0, 0, 1, 0, 10, usize::MAX / 2], arithmetic);
+            assert_agree(&divisors, 3, [usize::MAX, 0, 1, 0, 10, 5], arithmetic);
+            assert_agree(&divide, 2, [0, 0, usize::MAX, 0, 10, 0], arithmetic);
+            assert_agree(&divide, 2, [0, 0, usize::MAX / 256, 0, 10, 0], arithmetic);
+            // Without overflow every mode gives the same result.
+            assert_agree(&divisors, 3, [0, 0, 1, 0, 12, 3], arithmetic);
+        }
+        // Other modes never leave this loop, as no product can exceed the dividend.
+        assert_agree(
+            &divide,
+            2,
+            [0, 0, usize::MAX / 256 - 1, 0, usize::MAX, 0],
+            Arithmetic::Checked,
+        );
+
+        let mut machine: Machine = Machine::new(&divisors).unwrap();
+        machine.cpu = CPU::new([0, 0, 1, 0, 10, usize::MAX / 2]);
+        machine.ip = 3;
+        assert_eq!(
+            machine.run_optimized(&divisors, &optimize(&divisors)),
+            Err(Error::InvalidProgram(format!(
+                "instruction 3: {} * 3 overflows",
+                usize::MAX / 2
+            )))
+        );
+    }
+
+    #[test]
+    fn test_large_input() {
+        // Billions of steps when interpreted.
+        let machine = run(&sum_of_divisors(100_000), true);
+        assert_eq!(machine.registers()[0], 246_078);
+    }
+}
@@ -0,0 +1,345 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::compile::Compiled;
+use super::optimize::Shortcut;
+use super::trace::{TraceEvent, TraceSink};
+use super::{asm, Instruction, Word, CPU};
+use crate::error::{Error, Result};
+
+/// A program in the textual form with named opcodes, optionally binding a register to the
+/// instruction pointer:
+///
+/// ```text
+/// #ip 0
+/// seti 5 0 1
+/// addi 0 1 0
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    pub ip: Option<usize>,
+    pub instructions: Vec<Instruction>,
+}
+
+impl Program {
+    /// Parses program text; see `asm::assemble` for the accepted syntax.
+    pub fn parse(text: &str) -> Result<Program> {
+        asm::assemble(text)
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", asm::print(self))
+    }
+}
+
+/// Which engine runs a program.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Backend {
+    /// `CPU::execute`, one instruction at a time; the reference semantics.
+    Interpreter,
+    /// The interpreter, computing the loops `optimize::optimize` recognises in one go.
+    Optimized,
+    /// The threaded code prepared by `Compiled::new`.
+    Compiled,
+}
+
+/// A `CPU` running a `Program`, six registers wide unless stated otherwise. When the program
+/// binds a register to the instruction pointer, the pointer is written to that register before
+/// each instruction and read back after it, so instructions can jump by writing to the register.
+/// The pointer is incremented after the read, so leaving -1 in the register continues at the
+/// first instruction; a pointer that is still negative halts the program like any other
+/// pointer outside it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Machine<const N: usize = 6, W: Word = usize> {
+    pub cpu: CPU<N, W>,
+    pub ip: usize,
+    ip_register: Option<usize>,
+    steps: u64,
+}
+
+impl<const N: usize, W: Word> Machine<N, W> {
+    pub fn new(program: &Program) -> Result<Machine<N, W>> {
+        if let Some(register) = program.ip {
+            if register >= N {
+                return Err(Error::InvalidProgram(format!(
+                    "#ip {} names a register the machine does not have",
+                    register
+                )));
+            }
+        }
+        Ok(Machine {
+            cpu: CPU::new([W::default(); N]),
+            ip: 0,
+            ip_register: program.ip,
+            steps: 0,
+        })
+    }
+
+    pub fn registers(&self) -> &[W] {
+        self.cpu.registers()
+    }
+
+    pub fn halted(&self, program: &Program) -> bool {
+        self.ip >= program.instructions.len()
+    }
+
+    /// Number of instructions executed so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Executes the instruction at the instruction pointer. Returns `false` without doing
+    /// anything once the pointer has left the program.
+    pub fn step(&mut self, program: &Program) -> Result<bool> {
+        self.step_traced(program, None)
+    }
+
+    /// Like `step`, also reporting the executed instruction to `sink` if there is one.
+    pub fn step_traced(
+        &mut self,
+        program: &Program,
+        sink: Option<&mut dyn TraceSink<W>>,
+    ) -> Result<bool> {
+        if self.halted(program) {
+            return Ok(false);
+        }
+        let ip = self.ip;
+        let inst = program.instructions[ip];
+        if let Some(register) = self.ip_register {
+            self.cpu.r[register] = W::from_immediate(ip).ok_or_else(|| {
+                Error::InvalidProgram(format!("instruction {} does not fit in a register", ip))
+            })?;
+        }
+        let before = self.cpu;
+        self.cpu = self.cpu.execute(inst).map_err(|e| match e {
+            Error::InvalidProgram(message) => {
+                Error::InvalidProgram(format!("instruction {}: {}", ip, message))
+            }
+            e => e,
+        })?;
+        if let Some(sink) = sink {
+            sink.record(&TraceEvent {
+                step: self.steps,
+                ip,
+                instruction: inst,
+                before: before.registers(),
+                after: self.cpu.registers(),
+            })?;
+        }
+        self.ip = match self.ip_register {
+            Some(register) => self.cpu.r[register].next_index(),
+            None => self.ip.saturating_add(1),
+        };
+        self.steps += 1;
+        Ok(true)
+    }
+
+    /// Runs until the program halts and returns the number of instructions executed.
+    pub fn run(&mut self, program: &Program) -> Result<u64> {
+        let start = self.steps;
+        while self.step(program)? {}
+        Ok(self.steps - start)
+    }
+
+    /// Like `run`, reporting every executed instruction to `sink`.
+    pub fn run_traced(&mut self, program: &Program, sink: &mut dyn TraceSink<W>) -> Result<u64> {
+        let start = self.steps;
+        while self.step_traced(program, Some(&mut *sink))? {}
+        Ok(self.steps - start)
+    }
+
+    /// Like `run`, executing the closures of a compiled program instead of interpreting its
+    /// instructions. The result is the same as `run` on `compiled.program()`.
+    pub fn run_compiled(&mut self, compiled: &Compiled<N, W>) -> Result<u64> {
+        self.run_compiled_limit(compiled, u64::MAX)
+    }
+
+    /// Like `run_compiled`, stopping after at most `limit` instructions.
+    pub fn run_compiled_limit(&mut self, compiled: &Compiled<N, W>, limit: u64) -> Result<u64> {
+        if compiled.program.ip != self.ip_register || compiled.arithmetic != self.cpu.arithmetic() {
+            return Err(Error::InvalidProgram(
+                "the program was compiled for a different #ip binding or arithmetic".to_string(),
+            ));
+        }
+        let start = self.steps;
+        let stop = compiled.execute(&mut self.cpu.r, self.ip, limit);
+        self.ip = stop.pc;
+        self.steps += stop.steps;
+        if let (Some(register), Some(value)) = (self.ip_register, stop.ip) {
+            self.cpu.r[register] = value;
+        }
+        // Whatever is left, such as an instruction that fails or the last few steps before
+        // the limit, is interpreted, so errors and step counts are exactly those of `run`.
+        while self.steps - start < limit && self.step(&compiled.program)? {}
+        Ok(self.steps - start)
+    }
+}
+
+impl<const N: usize> Machine<N> {
+    /// Like `step`, but computes a whole loop found by `optimize::optimize` when the
+    /// instruction pointer is at its start. A shortcut counts as a single step.
+    pub fn step_optimized(
+        &mut self,
+        program: &Program,
+        shortcuts: &HashMap<usize, Shortcut>,
+    ) -> Result<bool> {
+        let exit = match (shortcuts.get(&self.ip), self.ip_register) {
+            (Some(shortcut), Some(register)) => {
+                let arithmetic = self.cpu.arithmetic();
+                shortcut.apply(self.cpu.registers_mut(), register, arithmetic)
+            }
+            _ => None,
+        };
+        match exit {
+            Some(exit) => {
+                self.ip = exit;
+                self.steps += 1;
+                Ok(true)
+            }
+            None => self.step(program),
+        }
+    }
+
+    /// Like `run`, taking shortcuts through the loops in `shortcuts`.
+    pub fn run_optimized(
+        &mut self,
+        program: &Program,
+        shortcuts: &HashMap<usize, Shortcut>,
+    ) -> Result<u64> {
+        let start = self.steps;
+        while self.step_optimized(program, shortcuts)? {}
+        Ok(self.steps - start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::day16::{Arithmetic, Opcode};
+
+    const EXAMPLE: &str = "#ip 0
+seti 5 0 1
+seti 6 0 2
+addi 0 1 0
+addr 1 2 3
+setr 1 0 0
+seti 8 0 4
+seti 9 0 5
+";
+
+    #[test]
+    fn test_parse() {
+        let program = Program::parse(EXAMPLE).unwrap();
+        assert_eq!(program.ip, Some(0));
+        assert_eq!(program.instructions.len(), 7);
+        assert_eq!(
+            program.instructions[2],
+            Instruction {
+                opcode: Opcode::Addi,
+                a: 0,
+                b: 1,
+                c: 0
+            }
+        );
+
+        assert_eq!(
+            Program::parse("seti 1 2 3\nfoo 1 2 3").unwrap_err(),
+            Error::parse(2, 1, "unknown opcode \"foo\"")
+        );
+        assert_eq!(
+            Program::parse("  seti 1 x 3").unwrap_err(),
+            Error::parse(1, 10, "undefined label \"x\"")
+        );
+        assert_eq!(
+            Program::parse("seti 1 2").unwrap_err(),
+            Error::parse(1, 1, "seti takes 3 operands, found 2")
+        );
+        assert!(Program::parse("#ip 1\n#ip 2").is_err());
+    }
+
+    #[test]
+    fn test_run() {
+        let program = Program::parse(EXAMPLE).unwrap();
+        let mut machine: Machine = Machine::new(&program).unwrap();
+        assert_eq!(machine.run(&program), Ok(5));
+        assert_eq!(machine.registers(), &[6, 5, 6, 0, 0, 9]);
+        assert_eq!(machine.ip, 7);
+        assert_eq!(machine.step(&program), Ok(false));
+    }
+
+    #[test]
+    fn test_run_without_ip_binding() {
+        let program = Program::parse("seti 3 0 0\naddi 0 4 1\nmulr 0 1 2").unwrap();
+        let mut machine: Machine<4> = Machine::new(&program).unwrap();
+        assert_eq!(machine.run(&program), Ok(3));
+        assert_eq!(machine.registers(), &[3, 7, 21, 0]);
+    }
+
+    #[test]
+    fn test_overflow() {
+        // Squares r0 until it no longer fits.
+        let program = Program::parse("#ip 1\nseti 3 0 0\nmulr 0 0 0\nseti 0 0 1").unwrap();
+        let mut machine: Machine = Machine::new(&program).unwrap();
+        let error = machine.run(&program).unwrap_err();
+        assert!(error.to_string().contains("instruction 1: "));
+        assert!(error.to_string().ends_with("overflows"));
+
+        let mut machine: Machine = Machine::new(&program).unwrap();
+        machine.cpu = machine.cpu.with_arithmetic(Arithmetic::Saturating);
+        for _ in 0..20 {
+            machine.step(&program).unwrap();
+        }
+        assert_eq!(machine.registers()[0], usize::MAX);
+    }
+
+    #[test]
+    fn test_signed_registers() {
+        // A pointer still negative after the increment leaves the program.
+        let program = Program::parse("#ip 1\naddr 0 1 1\nseti 7 0 2").unwrap();
+        let mut machine: Machine<6, i64> = Machine::new(&program).unwrap();
+        machine.cpu.registers_mut()[0] = -5;
+        assert_eq!(machine.run(&program), Ok(1));
+        assert!(machine.halted(&program));
+        assert_eq!(machine.registers(), &[-5, -5, 0, 0, 0, 0]);
+
+        // Leaving -1 in the pointer register jumps back to the first instruction, so this
+        // counts r1 up to 3 before skipping the jump.
+        let program = Program::parse(
+            "#ip 0
+            addi 1 1 1
+            gtri 1 2 2
+            addr 2 0 0
+            setr 3 0 0
+            seti 7 0 4",
+        )
+        .unwrap();
+        let start = CPU::new([0, 0, 0, -1, 0, 0]);
+        let mut machine: Machine<6, i64> = Machine::new(&program).unwrap();
+        machine.cpu = start;
+        assert_eq!(machine.run(&program), Ok(12));
+        assert_eq!(machine.registers(), &[4, 3, 1, -1, 7, 0]);
+
+        let mut compiled: Machine<6, i64> = Machine::new(&program).unwrap();
+        compiled.cpu = start;
+        let code = Compiled::new(&program, Arithmetic::Checked);
+        assert_eq!(compiled.run_compiled(&code), Ok(12));
+        assert_eq!(compiled, machine);
+    }
+
+    #[test]
+    fn test_bad_registers() {
+        let program = Program::parse("#ip 6\nseti 0 0 0").unwrap();
+        assert!(Machine::<6>::new(&program).is_err());
+
+        let program = Program::parse("seti 0 0 1\nseti 0 0 6").unwrap();
+        let mut machine: Machine = Machine::new(&program).unwrap();
+        assert_eq!(
+            machine.run(&program),
+            Err(Error::InvalidProgram(
+                "instruction 1: register 6 is out of range for a CPU with 6 registers".to_string()
+            ))
+        );
+    }
+}
@@ -0,0 +1,218 @@
+use std::io::Write;
+
+use super::disasm;
+use super::program::Program;
+use super::{Instruction, Word};
+use crate::error::{Error, Result};
+use crate::runner::json_string;
+
+/// One executed instruction, with the registers around it. `before` already holds the
+/// instruction pointer in its bound register.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEvent<'a, W = usize> {
+    pub step: u64,
+    pub ip: usize,
+    pub instruction: Instruction,
+    pub before: &'a [W],
+    pub after: &'a [W],
+}
+
+/// Receives every instruction a `Machine` executes through `run_traced` or `step_traced`.
+pub trait TraceSink<W = usize> {
+    fn record(&mut self, event: &TraceEvent<W>) -> Result<()>;
+}
+
+fn write_error(e: std::io::Error) -> Error {
+    Error::io("<trace>", &e)
+}
+
+fn join<W: Word>(registers: &[W], separator: &str) -> String {
+    registers
+        .iter()
+        .map(|r| r.to_string())
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// Writes the trace as CSV, one column per register before and after. The header is written
+/// with the first event, once the register count is known.
+pub struct CsvTrace<W: Write> {
+    out: W,
+    header: bool,
+}
+
+impl<W: Write> CsvTrace<W> {
+    pub fn new(out: W) -> Self {
+        CsvTrace { out, header: false }
+    }
+}
+
+impl<W: Write, V: Word> TraceSink<V> for CsvTrace<W> {
+    fn record(&mut self, event: &TraceEvent<V>) -> Result<()> {
+        if !self.header {
+            let mut columns = vec!["step", "ip", "opcode", "a", "b", "c"]
+                .into_iter()
+                .map(String::from)
+                .collect::<Vec<_>>();
+            for when in &["before", "after"] {
+                columns.extend((0..event.before.len()).map(|i| format!("r{}_{}", i, when)));
+            }
+            writeln!(self.out, "{}", columns.join(",")).map_err(write_error)?;
+            self.header = true;
+        }
+        let inst = event.instruction;
+        writeln!(
+            self.out,
+            "{},{},{},{},{},{},{},{}",
+            event.step,
+            event.ip,
+            inst.opcode.name(),
+            inst.a,
+            inst.b,
+            inst.c,
+            join(event.before, ","),
+            join(event.after, ",")
+        )
+        .map_err(write_error)
+    }
+}
+
+/// Writes the trace as JSON lines, one object per event.
+pub struct JsonTrace<W: Write> {
+    out: W,
+}
+
+impl<W: Write> JsonTrace<W> {
+    pub fn new(out: W) -> Self {
+        JsonTrace { out }
+    }
+}
+
+impl<W: Write, V: Word> TraceSink<V> for JsonTrace<W> {
+    fn record(&mut self, event: &TraceEvent<V>) -> Result<()> {
+        writeln!(
+            self.out,
+            "{{\"step\": {}, \"ip\": {}, \"instruction\": {}, \"before\": [{}], \"after\": [{}]}}",
+            event.step,
+            event.ip,
+            json_string(&event.instruction.to_string()),
+            join(event.before, ", "),
+            join(event.after, ", ")
+        )
+        .map_err(write_error)
+    }
+}
+
+/// Counts how often each instruction runs, to find the hot loops of a program.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Profile {
+    counts: Vec<u64>,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Profile::default()
+    }
+
+    /// Executions per instruction index. Indices past the last executed instruction are
+    /// left out.
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// The program listing with each instruction's execution count and share of the total.
+    pub fn report(&self, program: &Program) -> String {
+        let total = self.total().max(1) as f64;
+        let count_width = self.counts.iter().max().unwrap_or(&0).to_string().len();
+        let number_width = program
+            .instructions
+            .len()
+            .saturating_sub(1)
+            .to_string()
+            .len();
+        let mut out = String::new();
+        for (i, &inst) in program.instructions.iter().enumerate() {
+            let count = self.counts.get(i).cloned().unwrap_or(0);
+            out.push_str(&format!(
+                "{:>cw$} {:>5.1}%  {:>nw$}: {}\n",
+                count,
+                100.0 * count as f64 / total,
+                i,
+                disasm::format_instruction(inst, program.ip),
+                cw = count_width,
+                nw = number_width
+            ));
+        }
+        out
+    }
+}
+
+impl<V> TraceSink<V> for Profile {
+    fn record(&mut self, event: &TraceEvent<V>) -> Result<()> {
+        if self.counts.len() <= event.ip {
+            self.counts.resize(event.ip + 1, 0);
+        }
+        self.counts[event.ip] += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::day16::program::Machine;
+
+    // Counts r1 up to 10, jumping back to instruction 1 until r1 > 9.
+    const COUNTER: &str = "#ip 3\nseti 0 0 1\naddi 1 1 1\ngtri 1 9 2\naddr 2 3 3\nseti 0 0 3";
+
+    fn traced(sink: &mut dyn TraceSink) -> Program {
+        let program = Program::parse("seti 2 0 0\nmuli 0 3 1").unwrap();
+        let mut machine: Machine<2> = Machine::new(&program).unwrap();
+        assert_eq!(machine.run_traced(&program, sink), Ok(2));
+        program
+    }
+
+    #[test]
+    fn test_csv() {
+        let mut out = vec![];
+        traced(&mut CsvTrace::new(&mut out));
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "step,ip,opcode,a,b,c,r0_before,r1_before,r0_after,r1_after
+0,0,seti,2,0,0,0,0,2,0
+1,1,muli,0,3,1,2,0,2,6
+"
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let mut out = vec![];
+        traced(&mut JsonTrace::new(&mut out));
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(
+            out.lines().nth(1),
+            Some(
+                r#"{"step": 1, "ip": 1, "instruction": "muli 0 3 1", "before": [2, 0], "after": [2, 6]}"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_profile() {
+        let program = Program::parse(COUNTER).unwrap();
+        let mut machine: Machine = Machine::new(&program).unwrap();
+        let mut profile = Profile::new();
+        machine.run_traced(&program, &mut profile).unwrap();
+        assert_eq!(profile.counts(), &[1, 10, 10, 10, 9]);
+        assert_eq!(profile.total(), machine.steps());
+        assert_eq!(
+            profile.report(&program).lines().nth(1),
+            Some("10  25.0%  1: addi r1 1 -> r1")
+        );
+    }
+}
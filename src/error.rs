@@ -0,0 +1,85 @@
+use std::fmt;
+use std::io;
+
+/// Everything that can go wrong between reading an input file and producing an answer.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// An input file could not be read.
+    Io { path: String, message: String },
+    /// Input text did not have the expected shape. Lines and columns are 1-based.
+    Parse {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// A program for the day 16 CPU cannot be run as given.
+    InvalidProgram(String),
+    /// The input is well formed, but the puzzle has no answer for it.
+    NoAnswer(String),
+    /// A solver panicked.
+    Panic(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn io(path: &str, err: &io::Error) -> Self {
+        Error::Io {
+            path: path.to_string(),
+            message: err.to_string(),
+        }
+    }
+
+    pub fn parse(line: usize, column: usize, message: impl Into<String>) -> Self {
+        Error::Parse {
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+
+    /// Moves a parse error down by `by` lines, for input sections parsed on their own.
+    pub fn offset_lines(self, by: usize) -> Self {
+        match self {
+            Error::Parse {
+                line,
+                column,
+                message,
+            } => Error::parse(line + by, column, message),
+            other => other,
+        }
+    }
+
+    /// Process exit code for the CLI, distinct per kind of failure.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Io { .. } => 2,
+            Error::Parse { .. } => 3,
+            Error::InvalidProgram(_) => 4,
+            Error::NoAnswer(_) => 5,
+            Error::Panic(_) => 101,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io { path, message } => write!(f, "cannot read {}: {}", path, message),
+            Error::Parse {
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "parse error at line {}, column {}: {}",
+                line, column, message
+            ),
+            Error::InvalidProgram(message) => write!(f, "invalid program: {}", message),
+            Error::NoAnswer(message) => write!(f, "no answer: {}", message),
+            Error::Panic(message) => write!(f, "panicked: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
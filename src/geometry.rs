@@ -0,0 +1,294 @@
+use std::cmp::Ordering;
+use std::ops::{Add, Index, IndexMut, Mul, Neg, Sub};
+
+use crate::utils::{self, Grid};
+
+macro_rules! point {
+    ($name:ident, $($field:ident),+) => {
+        #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+        pub struct $name {
+            $(pub $field: i64),+
+        }
+
+        impl $name {
+            pub fn new($($field: i64),+) -> Self {
+                $name { $($field),+ }
+            }
+
+            pub fn origin() -> Self {
+                Self::default()
+            }
+
+            /// Sum of the absolute coordinate differences.
+            pub fn manhattan(self, other: Self) -> i64 {
+                0 $(+ (self.$field - other.$field).abs())+
+            }
+        }
+
+        impl Add for $name {
+            type Output = Self;
+
+            fn add(self, other: Self) -> Self {
+                $name { $($field: self.$field + other.$field),+ }
+            }
+        }
+
+        impl Sub for $name {
+            type Output = Self;
+
+            fn sub(self, other: Self) -> Self {
+                $name { $($field: self.$field - other.$field),+ }
+            }
+        }
+
+        impl Mul<i64> for $name {
+            type Output = Self;
+
+            fn mul(self, k: i64) -> Self {
+                $name { $($field: self.$field * k),+ }
+            }
+        }
+
+        impl Neg for $name {
+            type Output = Self;
+
+            fn neg(self) -> Self {
+                $name { $($field: -self.$field),+ }
+            }
+        }
+    };
+}
+
+point!(Point2, x, y);
+point!(Point3, x, y, z);
+point!(Point4, x, y, z, w);
+
+impl Point2 {
+    /// Converts to a grid position, if both coordinates are non-negative.
+    pub fn to_grid(self) -> Option<utils::Point> {
+        if self.x >= 0 && self.y >= 0 {
+            Some((self.x as usize, self.y as usize))
+        } else {
+            None
+        }
+    }
+}
+
+impl From<utils::Point> for Point2 {
+    fn from((x, y): utils::Point) -> Self {
+        Point2::new(x as i64, y as i64)
+    }
+}
+
+/// Grids can be indexed by `Point2` as well, so code that moves around in signed coordinates
+/// need not convert. Points with a negative coordinate are outside every grid.
+impl<T> Grid<T> {
+    pub fn get_point(&self, p: Point2) -> Option<&T> {
+        self.get(p.to_grid()?)
+    }
+
+    pub fn get_point_mut(&mut self, p: Point2) -> Option<&mut T> {
+        self.get_mut(p.to_grid()?)
+    }
+}
+
+impl<T> Index<Point2> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, p: Point2) -> &T {
+        self.get_point(p).unwrap_or_else(|| {
+            panic!(
+                "{:?} is outside a {}x{} grid",
+                p,
+                self.width(),
+                self.height()
+            )
+        })
+    }
+}
+
+impl<T> IndexMut<Point2> for Grid<T> {
+    fn index_mut(&mut self, p: Point2) -> &mut T {
+        let (width, height) = (self.width(), self.height());
+        self.get_point_mut(p)
+            .unwrap_or_else(|| panic!("{:?} is outside a {}x{} grid", p, width, height))
+    }
+}
+
+/// Points are ordered in reading order: top to bottom, then left to right.
+impl Ord for Point2 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.y, self.x).cmp(&(other.y, other.x))
+    }
+}
+
+impl PartialOrd for Point2 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// An axis-aligned rectangle of lattice points. Both corners are inclusive, so a box from
+/// `(1, 1)` to `(2, 3)` holds six points.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct BoundingBox {
+    pub min: Point2,
+    pub max: Point2,
+}
+
+impl BoundingBox {
+    /// The box spanned by two opposite corners, in any order.
+    pub fn new(a: Point2, b: Point2) -> Self {
+        BoundingBox {
+            min: Point2::new(a.x.min(b.x), a.y.min(b.y)),
+            max: Point2::new(a.x.max(b.x), a.y.max(b.y)),
+        }
+    }
+
+    /// The smallest box containing every point, or `None` for no points.
+    pub fn from_points(points: impl IntoIterator<Item = Point2>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        Some(points.fold(BoundingBox::new(first, first), |bb, p| {
+            bb.union(BoundingBox::new(p, p))
+        }))
+    }
+
+    pub fn width(&self) -> i64 {
+        self.max.x - self.min.x + 1
+    }
+
+    pub fn height(&self) -> i64 {
+        self.max.y - self.min.y + 1
+    }
+
+    /// Number of lattice points in the box.
+    pub fn area(&self) -> i64 {
+        self.width() * self.height()
+    }
+
+    pub fn contains(&self, p: Point2) -> bool {
+        self.min.x <= p.x && p.x <= self.max.x && self.min.y <= p.y && p.y <= self.max.y
+    }
+
+    /// Whether the point lies on the border of the box, e.g. to spot unbounded regions.
+    pub fn on_edge(&self, p: Point2) -> bool {
+        self.contains(p)
+            && (p.x == self.min.x || p.x == self.max.x || p.y == self.min.y || p.y == self.max.y)
+    }
+
+    /// The smallest box containing both boxes.
+    pub fn union(self, other: Self) -> Self {
+        BoundingBox {
+            min: Point2::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y)),
+            max: Point2::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y)),
+        }
+    }
+
+    /// The overlap of both boxes, if they share any point.
+    pub fn intersection(self, other: Self) -> Option<Self> {
+        let min = Point2::new(self.min.x.max(other.min.x), self.min.y.max(other.min.y));
+        let max = Point2::new(self.max.x.min(other.max.x), self.max.y.min(other.max.y));
+        if min.x <= max.x && min.y <= max.y {
+            Some(BoundingBox { min, max })
+        } else {
+            None
+        }
+    }
+
+    /// Every point in the box, in reading order.
+    pub fn points(self) -> impl Iterator<Item = Point2> {
+        (self.min.y..=self.max.y)
+            .flat_map(move |y| (self.min.x..=self.max.x).map(move |x| Point2::new(x, y)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_points() {
+        let a = Point2::new(1, 6);
+        let b = Point2::new(8, 3);
+        assert_eq!(a.manhattan(b), 10);
+        assert_eq!(a + b, Point2::new(9, 9));
+        assert_eq!(a - b, Point2::new(-7, 3));
+        assert_eq!(a * 3, Point2::new(3, 18));
+        assert_eq!(-a, Point2::new(-1, -6));
+        assert_eq!(Point2::origin().manhattan(a), 7);
+
+        let p = Point3::new(0, 0, 0);
+        assert_eq!(p.manhattan(Point3::new(1, -2, 3)), 6);
+        let q = Point4::new(0, 0, 0, 0);
+        assert_eq!(q.manhattan(Point4::new(-1, 2, -3, 4)), 10);
+        assert_eq!(Point4::new(1, 1, 1, 1) * -2, Point4::new(-2, -2, -2, -2));
+    }
+
+    #[test]
+    fn test_reading_order() {
+        let mut points = vec![
+            Point2::new(2, 1),
+            Point2::new(0, 2),
+            Point2::new(5, 0),
+            Point2::new(1, 1),
+        ];
+        points.sort();
+        assert_eq!(
+            points,
+            vec![
+                Point2::new(5, 0),
+                Point2::new(1, 1),
+                Point2::new(2, 1),
+                Point2::new(0, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_grid_conversion() {
+        assert_eq!(Point2::new(3, 4).to_grid(), Some((3, 4)));
+        assert_eq!(Point2::new(-1, 4).to_grid(), None);
+        assert_eq!(Point2::from((3, 4)), Point2::new(3, 4));
+
+        let mut grid = Grid::parse("ab\ncd").unwrap();
+        assert_eq!(grid[Point2::new(1, 0)], 'b');
+        assert_eq!(grid.get_point(Point2::new(0, 1)), Some(&'c'));
+        assert_eq!(grid.get_point(Point2::new(-1, 0)), None);
+        assert_eq!(grid.get_point(Point2::new(2, 0)), None);
+        grid[Point2::new(1, 1)] = 'x';
+        assert_eq!(grid[(1, 1)], 'x');
+    }
+
+    #[test]
+    fn test_bounding_box() {
+        // The claims from the day 3 example.
+        let claim1 = BoundingBox::new(Point2::new(1, 3), Point2::new(4, 6));
+        let claim2 = BoundingBox::new(Point2::new(3, 1), Point2::new(6, 4));
+        let claim3 = BoundingBox::new(Point2::new(5, 5), Point2::new(6, 6));
+        assert_eq!(claim1.area(), 16);
+        assert_eq!(claim1.intersection(claim2).map(|bb| bb.area()), Some(4));
+        assert_eq!(claim1.intersection(claim3), None);
+        assert_eq!(
+            claim1.union(claim3),
+            BoundingBox::new(Point2::new(1, 3), Point2::new(6, 6))
+        );
+
+        assert!(claim1.contains(Point2::new(4, 6)));
+        assert!(!claim1.contains(Point2::new(5, 6)));
+        assert!(claim1.on_edge(Point2::new(1, 4)));
+        assert!(!claim1.on_edge(Point2::new(2, 4)));
+        assert_eq!(claim3.points().count(), 4);
+
+        let bb = BoundingBox::from_points(vec![
+            Point2::new(1, 1),
+            Point2::new(1, 6),
+            Point2::new(8, 3),
+            Point2::new(-3, 4),
+        ])
+        .unwrap();
+        assert_eq!(bb, BoundingBox::new(Point2::new(-3, 1), Point2::new(8, 6)));
+        assert_eq!((bb.width(), bb.height()), (12, 6));
+        assert_eq!(BoundingBox::from_points(vec![]), None);
+    }
+}
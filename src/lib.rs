@@ -0,0 +1,14 @@
+//! Advent of Code 2018 solutions as a library: each `dayNN` module implements
+//! [`solution::Solution`], and the shared parsing helpers live in [`utils`].
+
+pub mod answers;
+pub mod bench;
+pub mod day01;
+pub mod day02;
+pub mod day05;
+pub mod day16;
+pub mod error;
+pub mod geometry;
+pub mod runner;
+pub mod solution;
+pub mod utils;
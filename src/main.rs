@@ -1,33 +1,549 @@
-mod day01;
-mod day02;
-mod day05;
-mod day16;
-mod utils;
-
-use clap::{App, Arg};
+use advent_of_code::day16::compile::Compiled;
+use advent_of_code::day16::debugger::Debugger;
+use advent_of_code::day16::program::{Backend, Machine, Program};
+use advent_of_code::day16::trace::{CsvTrace, JsonTrace, Profile, TraceSink};
+use advent_of_code::day16::{self, Arithmetic, Day16};
+use advent_of_code::day16::{decompile, generate, halting, optimize};
+use advent_of_code::solution::{Registry, Solution};
+use advent_of_code::{answers, bench, error, runner, solution, utils};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use std::io;
+use std::num::NonZeroUsize;
+use std::panic;
 use std::process::exit;
+use std::time::Instant;
+
+fn days_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("days")
+        .help("Day to run: a number, a range like 1-5, a list like 1,2,16, or all")
+        .required(true)
+        .index(1)
+}
+
+fn input_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("input")
+        .short("i")
+        .long("input")
+        .value_name("FILE")
+        .help("Input file, or - for stdin (default: inputs/NN/input.txt)")
+}
+
+fn program_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("program")
+        .long("program")
+        .value_name("FILE")
+        .conflicts_with("input")
+        .help("Assembly text to run on a six-register machine instead")
+}
+
+fn arithmetic_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("arithmetic")
+        .long("arithmetic")
+        .value_name("MODE")
+        .possible_values(&["checked", "wrapping", "saturating"])
+        .default_value("checked")
+        .help("What additions and multiplications do on overflow")
+}
 
 fn main() {
     let matches = App::new("Advent of Code 2018")
         .version("0.1")
         .author("Isaac Slavitt")
-        .arg(Arg::with_name("day").help("Day to run").index(1))
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .arg(days_arg())
+        .arg(input_arg())
+        .arg(
+            Arg::with_name("part")
+                .short("p")
+                .long("part")
+                .value_name("PART")
+                .possible_values(&["1", "2"])
+                .help("Only run this part"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .short("f")
+                .long("format")
+                .value_name("FORMAT")
+                .possible_values(&["table", "json"])
+                .default_value("table")
+                .help("Output format"),
+        )
+        .arg(
+            Arg::with_name("check")
+                .long("check")
+                .help("Compare answers against the recorded answers file"),
+        )
+        .arg(
+            Arg::with_name("answers")
+                .long("answers")
+                .value_name("FILE")
+                .default_value("answers.toml")
+                .help("Recorded answers used by --check"),
+        )
+        .subcommand(
+            SubCommand::with_name("bench")
+                .about("Time each phase of a day over repeated runs")
+                .arg(days_arg())
+                .arg(input_arg())
+                .arg(
+                    Arg::with_name("iterations")
+                        .short("n")
+                        .long("iterations")
+                        .value_name("N")
+                        .default_value("10")
+                        .help("Number of runs per phase"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("disasm")
+                .about("Print the day 16 test program with resolved opcode names")
+                .arg(input_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("debug")
+                .about("Step through the day 16 test program or an assembled #ip program")
+                .arg(input_arg())
+                .arg(program_arg())
+                .arg(arithmetic_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("decompile")
+                .about("Print the day 16 test program or an assembled #ip program as pseudo-code")
+                .arg(input_arg())
+                .arg(program_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("execute")
+                .about("Run the day 16 test program or an assembled #ip program to completion")
+                .arg(input_arg())
+                .arg(program_arg())
+                .arg(arithmetic_arg())
+                .arg(
+                    Arg::with_name("backend")
+                        .long("backend")
+                        .value_name("BACKEND")
+                        .possible_values(&["interpreter", "optimized", "compiled"])
+                        .default_value("compiled")
+                        .help(
+                            "Interpret instructions one by one, interpret them with known loops \
+                             computed directly, or compile them to closures first",
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("generate")
+                .about("Write a day 16 input for a secret opcode mapping to standard output")
+                .arg(
+                    Arg::with_name("seed")
+                        .long("seed")
+                        .value_name("N")
+                        .default_value("0")
+                        .help("Seed for the samples, the program and any random mapping"),
+                )
+                .arg(
+                    Arg::with_name("mapping")
+                        .long("mapping")
+                        .value_name("OPCODES")
+                        .help("Comma-separated opcode names for numbers 0 to 15; random if absent"),
+                )
+                .arg(
+                    Arg::with_name("samples")
+                        .long("samples")
+                        .value_name("N")
+                        .default_value("800")
+                        .help("Number of Before/After samples"),
+                )
+                .arg(
+                    Arg::with_name("program-length")
+                        .long("program-length")
+                        .value_name("N")
+                        .default_value("900")
+                        .help("Number of instructions in the test program"),
+                )
+                .arg(
+                    Arg::with_name("max-value")
+                        .long("max-value")
+                        .value_name("N")
+                        .default_value("3")
+                        .help("Largest register value and immediate in samples and the program"),
+                )
+                .arg(
+                    Arg::with_name("unsampled")
+                        .long("unsampled")
+                        .value_name("NUMBERS")
+                        .help("Comma-separated opcode numbers that get no samples"),
+                )
+                .arg(
+                    Arg::with_name("ambiguity")
+                        .long("ambiguity")
+                        .value_name("N")
+                        .default_value("1")
+                        .help("Keep the most ambiguous of N candidate samples each time"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("halting")
+                .about("Find the register values that make an #ip program halt soonest and latest")
+                .arg(program_arg().required(true))
+                .arg(
+                    Arg::with_name("register")
+                        .short("r")
+                        .long("register")
+                        .value_name("R")
+                        .default_value("0")
+                        .help("Register whose comparisons are watched"),
+                )
+                .arg(
+                    Arg::with_name("max-steps")
+                        .long("max-steps")
+                        .value_name("N")
+                        .default_value("100000000")
+                        .help("Give up after this many steps"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("trace")
+                .about("Run the day 16 test program or an assembled #ip program, logging each step")
+                .arg(input_arg())
+                .arg(program_arg())
+                .arg(arithmetic_arg())
+                .arg(
+                    Arg::with_name("format")
+                        .short("f")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .possible_values(&["csv", "json"])
+                        .default_value("csv")
+                        .help("Trace format: CSV or JSON lines"),
+                )
+                .arg(
+                    Arg::with_name("profile")
+                        .long("profile")
+                        .help("Print execution counts per instruction instead of the trace"),
+                ),
+        )
         .get_matches();
 
-    // You can check the value provided by positional arguments, or option arguments
-    if let Some(d) = matches.value_of("day") {
-        let d: i32 = d.parse().expect("Must be a number");
-        match d {
-            1 => day01::run(),
-            2 => day02::run(),
-            5 => day05::run(),
-            16 => day16::run(),
-            _ => {
-                println!("Day not found");
-                exit(1);
-            }
+    let registry = solution::registry();
+    match matches.subcommand() {
+        ("bench", Some(sub)) => run_bench(sub, &registry),
+        ("disasm", Some(sub)) => run_disasm(sub),
+        ("debug", Some(sub)) => run_debug(sub),
+        ("decompile", Some(sub)) => run_decompile(sub),
+        ("execute", Some(sub)) => run_execute(sub),
+        ("generate", Some(sub)) => run_generate(sub),
+        ("halting", Some(sub)) => run_halting(sub),
+        ("trace", Some(sub)) => run_trace(sub),
+        _ => run(&matches, &registry),
+    }
+}
+
+/// Resolves the `days` argument, rejecting `--input` when it would apply to several days.
+fn select_days(matches: &ArgMatches, registry: &Registry) -> Vec<u32> {
+    let days = match runner::select_days(matches.value_of("days").unwrap(), registry) {
+        Ok(days) => days,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
         }
-    } else {
+    };
+    if days.len() > 1 && matches.is_present("input") {
+        eprintln!("--input can only be used with a single day");
         exit(1);
     }
+    days
+}
+
+fn input_path(matches: &ArgMatches, day: u32) -> String {
+    match matches.value_of("input") {
+        Some(path) => path.to_string(),
+        None => utils::default_input_path(day),
+    }
+}
+
+fn run_bench(matches: &ArgMatches, registry: &Registry) {
+    let iterations: NonZeroUsize = match matches.value_of("iterations").unwrap().parse() {
+        Ok(n) => n,
+        Err(_) => {
+            eprintln!("--iterations must be a positive number");
+            exit(1);
+        }
+    };
+    for day in select_days(matches, registry) {
+        let path = input_path(matches, day);
+        let report = utils::read_input(&path)
+            .and_then(|text| bench::bench_day(day, registry.get(day).unwrap(), &text, iterations));
+        match report {
+            Ok(report) => println!("{}", bench::render_report(&report)),
+            Err(e) => {
+                eprintln!("day {}: {}", day, e);
+                exit(e.exit_code());
+            }
+        }
+    }
+}
+
+fn run_disasm(matches: &ArgMatches) {
+    let listing = utils::read_input(&input_path(matches, 16))
+        .and_then(|text| Day16.parse(&text))
+        .and_then(|(fixtures, program)| day16::listing(&fixtures, &program));
+    match listing {
+        Ok(listing) => print!("{}", listing),
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(e.exit_code());
+        }
+    }
+}
+
+/// The program for `debug` and `trace`: assembly text from `--program`, which runs on six
+/// registers, or else the day 16 test program, which runs on four. Returns the program and
+/// whether it wants six registers.
+fn load_program(matches: &ArgMatches) -> error::Result<(Program, bool)> {
+    match matches.value_of("program") {
+        Some(path) => Ok((Program::parse(&utils::read_input(path)?)?, true)),
+        None => {
+            let (fixtures, program) = Day16.parse(&utils::read_input(&input_path(matches, 16))?)?;
+            Ok((day16::test_program(&fixtures, &program)?, false))
+        }
+    }
+}
+
+fn exit_on_error(result: error::Result<()>) {
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        exit(e.exit_code());
+    }
+}
+
+/// A machine for `program` using the `--arithmetic` mode.
+fn machine<const N: usize>(matches: &ArgMatches, program: &Program) -> error::Result<Machine<N>> {
+    let arithmetic = match matches.value_of("arithmetic") {
+        Some("wrapping") => Arithmetic::Wrapping,
+        Some("saturating") => Arithmetic::Saturating,
+        _ => Arithmetic::Checked,
+    };
+    let mut machine: Machine<N> = Machine::new(program)?;
+    machine.cpu = machine.cpu.with_arithmetic(arithmetic);
+    Ok(machine)
+}
+
+fn run_debug(matches: &ArgMatches) {
+    exit_on_error(load_program(matches).and_then(|(program, wide)| {
+        if wide {
+            debug(&program, machine::<6>(matches, &program)?)
+        } else {
+            debug(&program, machine::<4>(matches, &program)?)
+        }
+    }));
+}
+
+fn debug<const N: usize>(program: &Program, machine: Machine<N>) -> error::Result<()> {
+    let stdin = io::stdin();
+    Debugger::new(program, machine)
+        .repl(stdin.lock(), io::stdout())
+        .map_err(|e| error::Error::io("<stdin>", &e))
+}
+
+fn run_decompile(matches: &ArgMatches) {
+    exit_on_error(load_program(matches).map(|(program, _)| {
+        print!("{}", decompile::render(&decompile::decompile(&program)));
+    }));
+}
+
+fn run_execute(matches: &ArgMatches) {
+    let backend = match matches.value_of("backend") {
+        Some("interpreter") => Backend::Interpreter,
+        Some("optimized") => Backend::Optimized,
+        _ => Backend::Compiled,
+    };
+    exit_on_error(load_program(matches).and_then(|(program, wide)| {
+        if wide {
+            execute(&program, machine::<6>(matches, &program)?, backend)
+        } else {
+            execute(&program, machine::<4>(matches, &program)?, backend)
+        }
+    }));
+}
+
+fn execute<const N: usize>(
+    program: &Program,
+    mut machine: Machine<N>,
+    backend: Backend,
+) -> error::Result<()> {
+    let start = Instant::now();
+    let steps = match backend {
+        Backend::Interpreter => machine.run(program)?,
+        Backend::Optimized => machine.run_optimized(program, &optimize::optimize(program))?,
+        Backend::Compiled => {
+            let compiled = Compiled::new(program, machine.cpu.arithmetic());
+            machine.run_compiled(&compiled)?
+        }
+    };
+    let registers: Vec<String> = machine.registers().iter().map(|r| r.to_string()).collect();
+    println!("registers: {}", registers.join(" "));
+    println!("{} steps in {:.3?}", steps, start.elapsed());
+    Ok(())
+}
+
+fn run_generate(matches: &ArgMatches) {
+    let number = |name: &str, text: &str| match text.trim().parse::<u64>() {
+        Ok(n) => n,
+        Err(_) => {
+            eprintln!("--{} must be a number", name);
+            exit(1);
+        }
+    };
+    let arg = |name: &str| number(name, matches.value_of(name).unwrap());
+    let seed = arg("seed");
+    let options = generate::Options {
+        samples: arg("samples") as usize,
+        program_len: arg("program-length") as usize,
+        max_value: arg("max-value") as usize,
+        unsampled: matches
+            .value_of("unsampled")
+            .map(|list| {
+                list.split(',')
+                    .map(|n| number("unsampled", n) as usize)
+                    .collect()
+            })
+            .unwrap_or_default(),
+        ambiguity: arg("ambiguity") as usize,
+    };
+    let mapping = match matches.value_of("mapping") {
+        Some(text) => generate::parse_mapping(text),
+        None => Ok(generate::shuffled_mapping(&mut utils::Rng::new(seed))),
+    };
+    exit_on_error(mapping.map(|mapping| {
+        let generated = generate::generate(&mapping, seed, &options);
+        print!("{}", generated.text);
+        // The answer key goes to standard error, so redirecting the input keeps it secret.
+        let names: Vec<&str> = mapping.iter().map(|op| op.name()).collect();
+        eprintln!("mapping: {}", names.join(","));
+        eprintln!("register 0 after the program: {}", generated.register0);
+    }));
+}
+
+fn run_halting(matches: &ArgMatches) {
+    let number = |name: &str| match matches.value_of(name).unwrap().parse::<u64>() {
+        Ok(n) => n,
+        Err(_) => {
+            eprintln!("--{} must be a number", name);
+            exit(1);
+        }
+    };
+    let (register, max_steps) = (number("register") as usize, number("max-steps"));
+    exit_on_error(load_program(matches).and_then(|(program, _)| {
+        let mut machine: Machine = Machine::new(&program)?;
+        let watch = halting::watch_register(&program, &mut machine, register, max_steps)?;
+        match watch.first() {
+            Some(first) => println!("halts soonest with r{} = {}", register, first),
+            None => println!("r{} is never compared against anything", register),
+        }
+        match watch.last() {
+            Some(last) => println!("halts latest with r{} = {}", register, last),
+            None => println!("the values did not repeat within {} steps", watch.steps),
+        }
+        println!(
+            "{} distinct values in {} steps",
+            watch.values.len(),
+            watch.steps
+        );
+        Ok(())
+    }));
+}
+
+fn run_trace(matches: &ArgMatches) {
+    exit_on_error(load_program(matches).and_then(|(program, wide)| {
+        if wide {
+            trace(matches, &program, machine::<6>(matches, &program)?)
+        } else {
+            trace(matches, &program, machine::<4>(matches, &program)?)
+        }
+    }));
+}
+
+fn trace<const N: usize>(
+    matches: &ArgMatches,
+    program: &Program,
+    mut machine: Machine<N>,
+) -> error::Result<()> {
+    if matches.is_present("profile") {
+        let mut profile = Profile::new();
+        machine.run_traced(program, &mut profile)?;
+        print!("{}", profile.report(program));
+        return Ok(());
+    }
+    let stdout = io::stdout();
+    let out = io::BufWriter::new(stdout.lock());
+    let mut sink: Box<dyn TraceSink> = match matches.value_of("format") {
+        Some("json") => Box::new(JsonTrace::new(out)),
+        _ => Box::new(CsvTrace::new(out)),
+    };
+    machine.run_traced(program, sink.as_mut())?;
+    Ok(())
+}
+
+fn run(matches: &ArgMatches, registry: &Registry) {
+    let days = select_days(matches, registry);
+
+    let parts: Vec<u8> = match matches.value_of("part") {
+        Some(part) => vec![part.parse().unwrap()],
+        None => vec![1, 2],
+    };
+
+    // Failures are reported in the table, so keep panic messages out of the output.
+    panic::set_hook(Box::new(|_| {}));
+
+    let mut runs = vec![];
+    for day in days {
+        let filename = input_path(matches, day);
+        runs.extend(runner::run_day(
+            day,
+            registry.get(day).unwrap(),
+            &filename,
+            &parts,
+        ));
+    }
+
+    if matches.is_present("check") {
+        let path = matches.value_of("answers").unwrap();
+        let known = match utils::read_input(path).and_then(|text| answers::parse_answers(&text)) {
+            Ok(known) => known,
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                exit(e.exit_code());
+            }
+        };
+        print!("{}", answers::render_report(&runs, &known));
+        report_errors(&runs);
+        if runs
+            .iter()
+            .any(|r| matches!(answers::verdict(r, &known), answers::Verdict::Fail(_)))
+        {
+            exit(1);
+        }
+        return;
+    }
+
+    match matches.value_of("format") {
+        Some("json") => print!("{}", runner::render_json(&runs)),
+        _ => print!("{}", runner::render_table(&runs)),
+    }
+    report_errors(&runs);
+}
+
+/// Prints each failed part to stderr and exits with the code of the first failure, if any.
+fn report_errors(runs: &[runner::PartRun]) {
+    let mut code = None;
+    for run in runs {
+        if let Err(e) = &run.answer {
+            eprintln!("day {} part {}: {}", run.day, run.part, e);
+            code = code.or_else(|| Some(e.exit_code()));
+        }
+    }
+    if let Some(code) = code {
+        exit(code);
+    }
 }
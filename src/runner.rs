@@ -0,0 +1,251 @@
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::{Duration, Instant};
+
+use crate::error::{Error, Result};
+use crate::solution::{Answer, DynSolution, Registry};
+use crate::utils;
+
+/// The outcome of one part of one day.
+#[derive(Debug, Clone)]
+pub struct PartRun {
+    pub day: u32,
+    pub part: u8,
+    pub answer: Result<Answer>,
+    pub elapsed: Duration,
+}
+
+/// Expands a day selection into registered day numbers. Accepts `all`, a single day (`16`), an
+/// inclusive range (`1-5`) or a comma-separated list of those (`1,2,5-16`).
+pub fn select_days(spec: &str, registry: &Registry) -> std::result::Result<Vec<u32>, String> {
+    if spec == "all" {
+        return Ok(registry.days().collect());
+    }
+    let mut days = vec![];
+    for item in spec.split(',') {
+        let item = item.trim();
+        if let Some(dash) = item.find('-') {
+            let from = parse_day(&item[..dash])?;
+            let to = parse_day(&item[dash + 1..])?;
+            if from > to {
+                return Err(format!("Empty day range: {}", item));
+            }
+            days.extend(registry.days().filter(|d| *d >= from && *d <= to));
+        } else {
+            let day = parse_day(item)?;
+            if registry.get(day).is_none() {
+                return Err(format!("Day not found: {}", day));
+            }
+            days.push(day);
+        }
+    }
+    days.sort();
+    days.dedup();
+    Ok(days)
+}
+
+fn parse_day(text: &str) -> std::result::Result<u32, String> {
+    text.trim()
+        .parse()
+        .map_err(|_| format!("Not a day number: {:?}", text))
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "solver panicked".to_string()
+    }
+}
+
+fn timed<T>(f: impl FnOnce() -> Result<T>) -> (Result<T>, Duration) {
+    let start = Instant::now();
+    let result = panic::catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|payload| Err(Error::Panic(panic_message(payload))));
+    (result, start.elapsed())
+}
+
+/// Reads and parses a day's input, then solves the requested parts. An error or panic anywhere is
+/// recorded as a failure of the affected parts instead of aborting the whole run.
+pub fn run_day(day: u32, solution: &dyn DynSolution, filename: &str, parts: &[u8]) -> Vec<PartRun> {
+    let (input, parse_time) = timed(|| solution.dyn_parse(&utils::read_input(filename)?));
+    let input = match input {
+        Ok(input) => input,
+        Err(e) => {
+            return parts
+                .iter()
+                .map(|&part| PartRun {
+                    day,
+                    part,
+                    answer: Err(e.clone()),
+                    elapsed: parse_time,
+                })
+                .collect()
+        }
+    };
+    parts
+        .iter()
+        .map(|&part| {
+            let (answer, elapsed) = match part {
+                1 => timed(|| solution.dyn_part1(input.as_ref())),
+                _ => timed(|| solution.dyn_part2(input.as_ref())),
+            };
+            PartRun {
+                day,
+                part,
+                answer,
+                elapsed,
+            }
+        })
+        .collect()
+}
+
+pub fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs_f64();
+    if secs >= 1.0 {
+        format!("{:.2} s", secs)
+    } else if secs >= 1e-3 {
+        format!("{:.2} ms", secs * 1e3)
+    } else {
+        format!("{:.2} µs", secs * 1e6)
+    }
+}
+
+/// Renders runs as an aligned text table.
+pub fn render_table(runs: &[PartRun]) -> String {
+    let answers: Vec<String> = runs
+        .iter()
+        .map(|r| match &r.answer {
+            Ok(answer) => answer.to_string(),
+            Err(e) => format!("FAILED: {}", e),
+        })
+        .collect();
+    let width = answers.iter().map(|a| a.len()).max().unwrap_or(0).max(6);
+
+    let mut out = format!(
+        "{:>3}  {:>4}  {:<width$}  {:>10}\n",
+        "Day",
+        "Part",
+        "Answer",
+        "Time",
+        width = width
+    );
+    for (run, answer) in runs.iter().zip(answers) {
+        out.push_str(&format!(
+            "{:>3}  {:>4}  {:<width$}  {:>10}\n",
+            run.day,
+            run.part,
+            answer,
+            format_duration(run.elapsed),
+            width = width
+        ));
+    }
+    out
+}
+
+pub(crate) fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Renders runs as a JSON array with one object per part. Numeric answers stay numbers, failed
+/// parts carry an `error` instead of an `answer`.
+pub fn render_json(runs: &[PartRun]) -> String {
+    let objects: Vec<String> = runs
+        .iter()
+        .map(|run| {
+            let result = match &run.answer {
+                Ok(Answer::Number(n)) => format!("\"answer\": {}", n),
+                Ok(Answer::Text(s)) => format!("\"answer\": {}", json_string(s)),
+                Err(e) => format!("\"error\": {}", json_string(&e.to_string())),
+            };
+            format!(
+                "  {{\"day\": {}, \"part\": {}, {}, \"duration_secs\": {}}}",
+                run.day,
+                run.part,
+                result,
+                run.elapsed.as_secs_f64()
+            )
+        })
+        .collect();
+    if objects.is_empty() {
+        return "[]\n".to_string();
+    }
+    format!("[\n{}\n]\n", objects.join(",\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solution;
+
+    #[test]
+    fn test_select_days() {
+        let registry = solution::registry();
+        assert_eq!(select_days("all", &registry), Ok(vec![1, 2, 5, 16]));
+        assert_eq!(select_days("1-5", &registry), Ok(vec![1, 2, 5]));
+        assert_eq!(select_days("16,1-2", &registry), Ok(vec![1, 2, 16]));
+        assert_eq!(select_days("2", &registry), Ok(vec![2]));
+        assert!(select_days("3", &registry).is_err());
+        assert!(select_days("5-1", &registry).is_err());
+        assert!(select_days("x", &registry).is_err());
+    }
+
+    #[test]
+    fn test_missing_input_fails() {
+        let registry = solution::registry();
+        let runs = run_day(1, registry.get(1).unwrap(), "does/not/exist.txt", &[1, 2]);
+        assert_eq!(runs.len(), 2);
+        assert!(runs
+            .iter()
+            .all(|r| matches!(r.answer, Err(Error::Io { .. }))));
+    }
+
+    #[test]
+    fn test_render_json() {
+        let runs = vec![
+            PartRun {
+                day: 2,
+                part: 1,
+                answer: Ok(Answer::Number(12)),
+                elapsed: Duration::from_millis(500),
+            },
+            PartRun {
+                day: 2,
+                part: 2,
+                answer: Ok(Answer::from("say \"hi\"")),
+                elapsed: Duration::from_secs(2),
+            },
+            PartRun {
+                day: 5,
+                part: 1,
+                answer: Err(Error::Panic("boom".to_string())),
+                elapsed: Duration::from_secs(0),
+            },
+        ];
+        assert_eq!(
+            render_json(&runs),
+            r#"[
+  {"day": 2, "part": 1, "answer": 12, "duration_secs": 0.5},
+  {"day": 2, "part": 2, "answer": "say \"hi\"", "duration_secs": 2},
+  {"day": 5, "part": 1, "error": "panicked: boom", "duration_secs": 0}
+]
+"#
+        );
+        assert_eq!(render_json(&[]), "[]\n");
+    }
+}
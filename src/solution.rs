@@ -0,0 +1,134 @@
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::error::Result;
+use crate::{day01, day02, day05, day16};
+
+/// A puzzle answer. Most days produce a number, a few produce text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Answer {
+    Number(i64),
+    Text(String),
+}
+
+impl fmt::Display for Answer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Answer::Number(n) => write!(f, "{}", n),
+            Answer::Text(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+impl From<i32> for Answer {
+    fn from(n: i32) -> Self {
+        Answer::Number(i64::from(n))
+    }
+}
+
+impl From<i64> for Answer {
+    fn from(n: i64) -> Self {
+        Answer::Number(n)
+    }
+}
+
+impl From<usize> for Answer {
+    fn from(n: usize) -> Self {
+        Answer::Number(n as i64)
+    }
+}
+
+impl From<String> for Answer {
+    fn from(s: String) -> Self {
+        Answer::Text(s)
+    }
+}
+
+impl From<&str> for Answer {
+    fn from(s: &str) -> Self {
+        Answer::Text(s.to_string())
+    }
+}
+
+/// One day's puzzle: parse the input once, then answer both parts from it.
+pub trait Solution {
+    type Input;
+
+    fn parse(&self, text: &str) -> Result<Self::Input>;
+    fn part1(&self, input: &Self::Input) -> Result<Answer>;
+    fn part2(&self, input: &Self::Input) -> Result<Answer>;
+}
+
+/// Type-erased view of a `Solution`, so days with different input types can share a registry.
+pub trait DynSolution {
+    fn dyn_parse(&self, text: &str) -> Result<Box<dyn Any>>;
+    fn dyn_part1(&self, input: &dyn Any) -> Result<Answer>;
+    fn dyn_part2(&self, input: &dyn Any) -> Result<Answer>;
+}
+
+impl<S> DynSolution for S
+where
+    S: Solution,
+    S::Input: 'static,
+{
+    fn dyn_parse(&self, text: &str) -> Result<Box<dyn Any>> {
+        Ok(Box::new(self.parse(text)?))
+    }
+
+    fn dyn_part1(&self, input: &dyn Any) -> Result<Answer> {
+        self.part1(input.downcast_ref().expect("Input parsed by another day"))
+    }
+
+    fn dyn_part2(&self, input: &dyn Any) -> Result<Answer> {
+        self.part2(input.downcast_ref().expect("Input parsed by another day"))
+    }
+}
+
+/// All implemented days, keyed by day number.
+#[derive(Default)]
+pub struct Registry {
+    days: BTreeMap<u32, Box<dyn DynSolution>>,
+}
+
+impl Registry {
+    pub fn register<S: DynSolution + 'static>(&mut self, day: u32, solution: S) {
+        self.days.insert(day, Box::new(solution));
+    }
+
+    pub fn get(&self, day: u32) -> Option<&dyn DynSolution> {
+        self.days.get(&day).map(|s| s.as_ref())
+    }
+
+    pub fn days(&self) -> impl Iterator<Item = u32> + '_ {
+        self.days.keys().cloned()
+    }
+}
+
+/// Every day implemented so far. New days only need a line here.
+pub fn registry() -> Registry {
+    let mut registry = Registry::default();
+    registry.register(1, day01::Day01);
+    registry.register(2, day02::Day02);
+    registry.register(5, day05::Day05);
+    registry.register(16, day16::Day16);
+    registry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry() {
+        let registry = registry();
+        assert!(registry.get(16).is_some());
+        assert!(registry.get(3).is_none());
+
+        let day02 = registry.get(2).unwrap();
+        let input = day02
+            .dyn_parse("abcdef\nbababc\nabbcde\nabcccd\naabcdd\nabcdee\nababab")
+            .unwrap();
+        assert_eq!(day02.dyn_part1(input.as_ref()), Ok(Answer::Number(12)));
+    }
+}
@@ -1,18 +1,368 @@
+use crate::error::{Error, Result};
+use std::fmt;
 use std::fs::File;
+use std::io;
 use std::io::prelude::*;
+use std::ops::{Index, IndexMut};
 
-pub fn read_input(filename: &str) -> String {
-    let mut f = File::open(filename).expect("File not found");
+/// Reads a whole input file, or standard input when `filename` is `-`.
+pub fn read_input(filename: &str) -> Result<String> {
     let mut contents = String::new();
-    f.read_to_string(&mut contents)
-        .expect("Problem reading the file");
-    contents
+    let read = if filename == "-" {
+        io::stdin().read_to_string(&mut contents)
+    } else {
+        File::open(filename).and_then(|mut f| f.read_to_string(&mut contents))
+    };
+    read.map_err(|e| Error::io(filename, &e))?;
+    Ok(contents)
+}
+
+/// Where a day's input lives when no `--input` is given.
+pub fn default_input_path(day: u32) -> String {
+    format!("inputs/{:02}/input.txt", day)
 }
 
 pub fn str_to_trimmed_lines_vec(contents: &str) -> Vec<String> {
     contents
         .lines()
         .map(|l| l.trim().to_string())
-        .filter(|l| l.len() > 0)
+        .filter(|l| !l.is_empty())
         .collect()
 }
+
+/// 1-based column at which `part` starts within `line`. `part` must be a subslice of `line`,
+/// e.g. a token from `split_whitespace`.
+pub fn column_of(line: &str, part: &str) -> usize {
+    part.as_ptr() as usize - line.as_ptr() as usize + 1
+}
+
+/// A grid position as `(x, y)`: `x` is the column and `y` the row, both counted from the top left.
+/// For signed coordinates and point arithmetic use `geometry::Point2`, which indexes grids too.
+pub type Point = (usize, usize);
+
+const OFFSETS_4: [(isize, isize); 4] = [(0, -1), (-1, 0), (1, 0), (0, 1)];
+const OFFSETS_8: [(isize, isize); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+/// A rectangular grid of cells stored row by row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grid<T> {
+    width: usize,
+    height: usize,
+    cells: Vec<T>,
+}
+
+impl<T: Clone> Grid<T> {
+    pub fn new(width: usize, height: usize, fill: T) -> Self {
+        Grid {
+            width,
+            height,
+            cells: vec![fill; width * height],
+        }
+    }
+}
+
+impl<T> Grid<T> {
+    /// Parses a character map, one row per non-empty line. `f` converts each character to a
+    /// cell, returning `None` for characters that are not allowed. Every row must be as wide
+    /// as the first.
+    pub fn parse_with(text: &str, f: impl Fn(char) -> Option<T>) -> Result<Self> {
+        let mut cells = vec![];
+        let mut width = None;
+        let mut height = 0;
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.is_empty() {
+                continue;
+            }
+            let mut row_width = 0;
+            for (i, c) in line.chars().enumerate() {
+                let cell = f(c).ok_or_else(|| {
+                    Error::parse(n + 1, i + 1, format!("unexpected character {:?}", c))
+                })?;
+                cells.push(cell);
+                row_width += 1;
+            }
+            match width {
+                None => width = Some(row_width),
+                Some(w) if w != row_width => {
+                    return Err(Error::parse(
+                        n + 1,
+                        row_width.min(w) + 1,
+                        format!("row is {} wide, expected {}", row_width, w),
+                    ));
+                }
+                _ => {}
+            }
+            height += 1;
+        }
+        Ok(Grid {
+            width: width.unwrap_or(0),
+            height,
+            cells,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn contains(&self, (x, y): Point) -> bool {
+        x < self.width && y < self.height
+    }
+
+    pub fn get(&self, p: Point) -> Option<&T> {
+        if self.contains(p) {
+            Some(&self.cells[p.1 * self.width + p.0])
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self, p: Point) -> Option<&mut T> {
+        if self.contains(p) {
+            Some(&mut self.cells[p.1 * self.width + p.0])
+        } else {
+            None
+        }
+    }
+
+    fn offset(&self, (x, y): Point, (dx, dy): (isize, isize)) -> Option<Point> {
+        let x = (x as isize).checked_add(dx)?;
+        let y = (y as isize).checked_add(dy)?;
+        if x < 0 || y < 0 {
+            return None;
+        }
+        let p = (x as usize, y as usize);
+        if self.contains(p) {
+            Some(p)
+        } else {
+            None
+        }
+    }
+
+    /// The up to four orthogonal neighbours of `p` that lie inside the grid, in reading order.
+    pub fn neighbours4(&self, p: Point) -> impl Iterator<Item = Point> + '_ {
+        OFFSETS_4.iter().filter_map(move |&d| self.offset(p, d))
+    }
+
+    /// The up to eight orthogonal and diagonal neighbours of `p` inside the grid, in reading
+    /// order.
+    pub fn neighbours8(&self, p: Point) -> impl Iterator<Item = Point> + '_ {
+        OFFSETS_8.iter().filter_map(move |&d| self.offset(p, d))
+    }
+
+    pub fn row(&self, y: usize) -> Option<&[T]> {
+        if y < self.height {
+            Some(&self.cells[y * self.width..(y + 1) * self.width])
+        } else {
+            None
+        }
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[T]> {
+        // chunks() panics on a zero size, and an empty grid has no rows anyway.
+        self.cells.chunks(self.width.max(1))
+    }
+
+    pub fn column(&self, x: usize) -> Option<impl Iterator<Item = &T>> {
+        if x < self.width {
+            Some(self.cells.iter().skip(x).step_by(self.width))
+        } else {
+            None
+        }
+    }
+
+    /// All points in reading order: left to right, then top to bottom.
+    pub fn points(&self) -> impl Iterator<Item = Point> {
+        let width = self.width;
+        (0..self.height).flat_map(move |y| (0..width).map(move |x| (x, y)))
+    }
+
+    /// Every cell with its position, in reading order.
+    pub fn iter(&self) -> impl Iterator<Item = (Point, &T)> {
+        self.points().zip(self.cells.iter())
+    }
+
+    pub fn map<U>(&self, f: impl Fn(&T) -> U) -> Grid<U> {
+        Grid {
+            width: self.width,
+            height: self.height,
+            cells: self.cells.iter().map(f).collect(),
+        }
+    }
+
+    /// Renders the grid back to text, one line per row.
+    pub fn render_with(&self, f: impl Fn(&T) -> char) -> String {
+        let mut out = String::with_capacity((self.width + 1) * self.height);
+        for row in self.rows() {
+            out.extend(row.iter().map(&f));
+            out.push('\n');
+        }
+        out
+    }
+}
+
+impl Grid<char> {
+    /// Parses a character map as-is.
+    pub fn parse(text: &str) -> Result<Self> {
+        Grid::parse_with(text, Some)
+    }
+}
+
+impl<T> Index<Point> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, p: Point) -> &T {
+        self.get(p)
+            .unwrap_or_else(|| panic!("{:?} is outside a {}x{} grid", p, self.width, self.height))
+    }
+}
+
+impl<T> IndexMut<Point> for Grid<T> {
+    fn index_mut(&mut self, p: Point) -> &mut T {
+        let (width, height) = (self.width, self.height);
+        self.get_mut(p)
+            .unwrap_or_else(|| panic!("{:?} is outside a {}x{} grid", p, width, height))
+    }
+}
+
+impl fmt::Display for Grid<char> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.render_with(|&c| c))
+    }
+}
+
+/// A small seeded pseudo-random number generator (SplitMix64), for generated test data.
+/// The same seed always gives the same numbers, on every platform.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`. `n` must not be zero.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// Puts `items` in a random order (Fisher-Yates).
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grid_parse_and_render() {
+        let text = "#.|\n..#\r\n|#.\n\n";
+        let grid = Grid::parse(text).unwrap();
+        assert_eq!((grid.width(), grid.height()), (3, 3));
+        assert_eq!(grid[(2, 0)], '|');
+        assert_eq!(grid.get((0, 2)), Some(&'|'));
+        assert_eq!(grid.get((3, 0)), None);
+        assert_eq!(grid.to_string(), "#.|\n..#\n|#.\n");
+
+        assert_eq!(
+            Grid::parse("##\n#\n").unwrap_err(),
+            Error::parse(2, 2, "row is 1 wide, expected 2")
+        );
+        let walls = Grid::parse_with("#.\n.x", |c| match c {
+            '#' => Some(true),
+            '.' => Some(false),
+            _ => None,
+        });
+        assert_eq!(
+            walls.unwrap_err(),
+            Error::parse(2, 2, "unexpected character 'x'")
+        );
+    }
+
+    #[test]
+    fn test_grid_neighbours() {
+        let grid = Grid::new(3, 3, 0u8);
+        assert_eq!(
+            grid.neighbours4((1, 1)).collect::<Vec<_>>(),
+            vec![(1, 0), (0, 1), (2, 1), (1, 2)]
+        );
+        assert_eq!(
+            grid.neighbours4((0, 0)).collect::<Vec<_>>(),
+            vec![(1, 0), (0, 1)]
+        );
+        assert_eq!(grid.neighbours8((1, 1)).count(), 8);
+        assert_eq!(
+            grid.neighbours8((2, 2)).collect::<Vec<_>>(),
+            vec![(1, 1), (2, 1), (1, 2)]
+        );
+    }
+
+    #[test]
+    fn test_grid_views() {
+        let mut grid = Grid::parse("abc\ndef").unwrap();
+        assert_eq!(grid.row(1), Some(&['d', 'e', 'f'][..]));
+        assert_eq!(grid.row(2), None);
+        assert_eq!(
+            grid.column(1).unwrap().collect::<String>(),
+            "be".to_string()
+        );
+        assert!(grid.column(3).is_none());
+        assert_eq!(grid.points().nth(4), Some((1, 1)));
+
+        grid[(0, 0)] = 'z';
+        let upper = grid.map(|c| c.to_ascii_uppercase());
+        assert_eq!(upper.render_with(|&c| c), "ZBC\nDEF\n");
+        assert_eq!(
+            upper
+                .iter()
+                .filter(|(_, &c)| c > 'C')
+                .map(|(p, _)| p)
+                .collect::<Vec<_>>(),
+            vec![(0, 0), (0, 1), (1, 1), (2, 1)]
+        );
+    }
+
+    #[test]
+    fn test_rng() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        let xs: Vec<u64> = (0..5).map(|_| a.next_u64()).collect();
+        assert_eq!(xs, (0..5).map(|_| b.next_u64()).collect::<Vec<_>>());
+        assert_ne!(xs[0], Rng::new(43).next_u64());
+        assert!((0..1000).all(|_| a.below(7) < 7));
+
+        let mut items: Vec<usize> = (0..10).collect();
+        a.shuffle(&mut items);
+        assert_ne!(items, (0..10).collect::<Vec<_>>());
+        items.sort_unstable();
+        assert_eq!(items, (0..10).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,31 @@
+use advent_of_code::day02::part2;
+use advent_of_code::day16::{Instruction, Opcode, CPU};
+use advent_of_code::solution::{self, Answer};
+use advent_of_code::utils;
+
+#[test]
+fn test_cpu_from_outside_the_crate() {
+    let cpu = CPU::from_vec(&[1, 2, 0, 0]).unwrap();
+    let instruction = Instruction {
+        opcode: Opcode::Mulr,
+        a: 0,
+        b: 1,
+        c: 3,
+    };
+    assert_eq!(cpu.execute(instruction).unwrap().registers(), &[1, 2, 0, 2]);
+}
+
+#[test]
+fn test_helpers_from_outside_the_crate() {
+    assert_eq!(part2::distance("fghij", "fguij"), 1);
+    assert_eq!(utils::str_to_trimmed_lines_vec(" a \n\n b"), vec!["a", "b"]);
+}
+
+#[test]
+fn test_registry_from_outside_the_crate() {
+    let registry = solution::registry();
+    let day01 = registry.get(1).unwrap();
+    let input = day01.dyn_parse("+1\n-2\n+3\n+1").unwrap();
+    assert_eq!(day01.dyn_part1(input.as_ref()), Ok(Answer::Number(3)));
+    assert_eq!(day01.dyn_part2(input.as_ref()), Ok(Answer::Number(2)));
+}
@@ -0,0 +1,59 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Runs the binary with `args`, feeding `stdin`, and returns its standard output.
+fn run(args: &[&str], stdin: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_advent-of-code"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{:?} failed", args);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_execute_optimized() {
+    // The nested loops of the day 19 puzzle, summing the divisors of 100000 into r0. Plainly
+    // interpreted this takes tens of billions of steps.
+    let program = "#ip 3
+        seti 100000 0 4
+        seti 1 0 5
+outer:  seti 1 0 2
+inner:  mulr 5 2 1
+        eqrr 1 4 1
+        addr 1 3 3
+        addi 3 1 3
+        addr 5 0 0
+        addi 2 1 2
+        gtrr 2 4 1
+        addr 3 1 3
+        seti inner-1 0 3
+        addi 5 1 5
+        gtrr 5 4 1
+        addr 1 3 3
+        seti outer-1 0 3";
+    let output = run(
+        &["execute", "--program", "-", "--backend", "optimized"],
+        program,
+    );
+    let mut lines = output.lines();
+    assert_eq!(
+        lines.next(),
+        Some("registers: 246078 1 100001 15 100000 100001")
+    );
+    let steps: u64 = lines
+        .next()
+        .and_then(|line| line.split(' ').next())
+        .and_then(|steps| steps.parse().ok())
+        .unwrap();
+    assert!(steps < 1_000_000, "{} steps", steps);
+}